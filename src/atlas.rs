@@ -0,0 +1,114 @@
+use bevy::{
+    asset::LoadState,
+    prelude::*,
+    sprite::TextureAtlasBuilder
+};
+
+use crate::{
+    fog::Fog,
+    registry::{
+        BlockRegistry,
+        RenderClass
+    },
+    sky::SkyCubemap,
+    terrain_material::TerrainMaterial
+};
+
+use std::collections::HashMap;
+
+/// Folder whose textures are packed into the block atlas
+pub const BLOCK_TEXTURE_FOLDER: &str = "textures/block";
+
+/// The block textures which are still loading, removed once the atlas is built
+pub struct BlockTextureFolder(Vec<HandleUntyped>);
+
+/// All the block textures packed into one image, with one material for each render class
+pub struct BlockAtlas {
+    pub image: Handle<Image>,
+    materials: [Handle<TerrainMaterial>; 3],
+}
+
+impl BlockAtlas {
+    pub fn material(&self, class: RenderClass) -> Handle<TerrainMaterial> {
+        self.materials[class as usize].clone()
+    }
+
+    pub fn materials(&self) -> &[Handle<TerrainMaterial>] {
+        &self.materials
+    }
+}
+
+pub fn load_block_textures(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    match asset_server.load_folder(BLOCK_TEXTURE_FOLDER) {
+        Ok(handles) => commands.insert_resource(BlockTextureFolder(handles)),
+        Err(err) => error!("Block textures could not be loaded: {:?}", err),
+    }
+}
+
+/// Packs the block textures once they are all loaded and gives their uvs to the registry
+pub fn build_block_atlas(
+    mut commands: Commands,
+    folder: Option<Res<BlockTextureFolder>>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+    mut registry: ResMut<BlockRegistry>,
+    cubemap: Option<Res<SkyCubemap>>,
+    fog: Res<Fog>,
+) {
+    let (folder, cubemap) = match (folder, cubemap) {
+        (Some(folder), Some(cubemap)) => (folder, cubemap),
+        _ => return,
+    };
+    if asset_server.get_group_load_state(folder.0.iter().map(|handle| handle.id)) != LoadState::Loaded {
+        return;
+    }
+
+    let mut builder = TextureAtlasBuilder::default();
+    for handle in &folder.0 {
+        let handle = handle.clone().typed::<Image>();
+        if let Some(image) = images.get(&handle) {
+            builder.add_texture(handle, image);
+        }
+    }
+    let atlas = match builder.finish(&mut images) {
+        Ok(atlas) => atlas,
+        Err(err) => {
+            error!("Block atlas could not be built: {:?}", err);
+            commands.remove_resource::<BlockTextureFolder>();
+            return;
+        }
+    };
+
+    let mut uvs = HashMap::new();
+    for handle in &folder.0 {
+        let path = match asset_server.get_handle_path(handle.id) {
+            Some(path) => path.path().to_string_lossy().replace('\\', "/"),
+            None => continue,
+        };
+        if let Some(index) = atlas.get_texture_index(&handle.clone().typed::<Image>()) {
+            let rect = atlas.textures[index];
+            uvs.insert(path, [rect.min / atlas.size, rect.max / atlas.size]);
+        }
+    }
+    registry.set_texture_uvs(&uvs);
+
+    // in the order of RenderClass
+    let class_materials = [(AlphaMode::Opaque, 0.0), (AlphaMode::Mask(0.5), 0.5), (AlphaMode::Blend, 0.0)].map(|(alpha_mode, alpha_cutoff)| {
+        materials.add(TerrainMaterial {
+            sky: cubemap.image.clone(),
+            fog: *fog,
+            texture: atlas.texture.clone(),
+            alpha_cutoff,
+            alpha_mode,
+        })
+    });
+    commands.insert_resource(BlockAtlas {
+        image: atlas.texture.clone(),
+        materials: class_materials,
+    });
+    commands.remove_resource::<BlockTextureFolder>();
+}
\ No newline at end of file
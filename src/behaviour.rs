@@ -0,0 +1,50 @@
+use bevy::prelude::*;
+
+use crate::{
+    falling::spawn_falling_block,
+    registry::{
+        BlockBehaviour,
+        BlockRegistry,
+        AIR
+    },
+    tick::TickContext
+};
+
+/// Registers the simulation hooks of the built-in blocks
+pub fn register_behaviours(registry: &mut BlockRegistry) {
+    registry.register_behaviour("water", BlockBehaviour {
+        on_scheduled_tick: Some(water_flow),
+        neighbour_tick_delay: Some(5),
+        ..default()
+    });
+
+    let falling: Vec<String> = registry.defs()
+        .filter(|def| def.falls_with_gravity)
+        .map(|def| def.name.clone())
+        .collect();
+    for name in falling {
+        registry.register_behaviour(&name, BlockBehaviour {
+            on_scheduled_tick: Some(fall),
+            neighbour_tick_delay: Some(2),
+            ..default()
+        });
+    }
+}
+
+// water pours into the air below it, the new water block then keeps falling
+fn water_flow(context: &mut TickContext, pos: IVec3) {
+    let below = pos - IVec3::Y;
+    if context.world.get_block_opt(below) == Some(AIR) {
+        let id = context.world.get_block(pos);
+        context.world.set_block(below, id);
+    }
+}
+
+// an unsupported block leaves the grid and falls as an entity, the blocks above follow on their own neighbour tick
+fn fall(context: &mut TickContext, pos: IVec3) {
+    if context.world.get_block_opt(pos - IVec3::Y) == Some(AIR) {
+        let id = context.world.get_block(pos);
+        context.world.set_block(pos, AIR);
+        spawn_falling_block(context.commands, pos, id);
+    }
+}
\ No newline at end of file
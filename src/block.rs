@@ -2,22 +2,56 @@ use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
 use crate::{
+    hotbar::Hotbar,
+    interaction::InteractionRules,
+    model::{
+        self,
+        connections
+    },
     player::{
         PlayerStatus,
         SeenObject
     },
-    utils::decimal_round
+    registry::{
+        BlockRegistry,
+        StateId,
+        AIR
+    },
+    state::{
+        placement_properties,
+        Property,
+        StateProperties
+    },
+    world::{
+        BlockChanged,
+        Face,
+        VoxelWorld,
+        CHUNK_HEIGHT,
+        CHUNK_SIZE,
+        NEIGHBOURS
+    }
 };
 
+use std::collections::{HashMap, HashSet};
+
+/// The collider of a block, the block itself is drawn by its chunk mesh
 #[derive(Component, Clone, Copy)]
 pub struct Block {
-    pub textures: [Entity; 6],
-    pub coord: Vec3
+    pub coord: Vec3,
+    pub id: StateId
 }
 
+/// The collider entities of the blocks which can be reached, with the block and fence connections they were built for
+#[derive(Default)]
+pub struct BlockEntities(HashMap<IVec3, (Entity, StateId, u8)>);
+
+/// Number of crack textures shown while a block breaks, it breaks after the last one
+const BREAK_PHASES: u8 = 9;
+
 pub struct BlockBreaker {
     pub block: Block,
     pub block_id: Entity,
+    /// One overlay for each face, in the order of Face
     breaking_textures: [Entity; 6],
     pub phase: u8,
     pub elapsed_time: f32
@@ -33,7 +67,7 @@ impl BlockBreaker {
         block_id: Entity
     ) -> Self {
         let plane = meshes.add(Mesh::from(shape::Plane{ size: 1.0 }));
-        let texture_handle = asset_server.load("textures/block/breaking0.png");
+        let texture_handle = asset_server.load("textures/breaking/breaking0.png");
         let material = materials.add(StandardMaterial {
             base_color_texture: Some(texture_handle.clone()),
             alpha_mode: AlphaMode::Blend,
@@ -68,7 +102,7 @@ impl BlockBreaker {
             commands.entity(texture).despawn();
         }
         let plane = meshes.add(Mesh::from(shape::Plane{ size: 1.0 }));
-        let texture_handle = asset_server.load(&format!("textures/block/breaking{}.png", self.phase));
+        let texture_handle = asset_server.load(&format!("textures/breaking/breaking{}.png", self.phase));
         let material = materials.add(StandardMaterial {
             base_color_texture: Some(texture_handle.clone()),
             alpha_mode: AlphaMode::Blend,
@@ -87,7 +121,7 @@ impl BlockBreaker {
         let mut transform;
         let mut breaking_textures = [Entity::from_raw(0); 6];
         let (x, y, z) = (coord.x, coord.y, coord.z);
-        breaking_textures[0] = {
+        breaking_textures[Face::East as usize] = {
             transform = Transform {
                 translation: Vec3::new(x+0.5, y, z),
                 rotation: Quat::from_rotation_x(-std::f32::consts::PI / 2.0),
@@ -103,14 +137,14 @@ impl BlockBreaker {
             }).id()
         };
         //==========
-        breaking_textures[1] = commands.spawn_bundle(PbrBundle {
+        breaking_textures[Face::Up as usize] = commands.spawn_bundle(PbrBundle {
             mesh: mesh.clone(),
             material: material.clone(),
             transform: Transform::from_xyz(x, y+0.5, z),
             ..default()
         }).id();
         //==========
-        breaking_textures[2] = {
+        breaking_textures[Face::South as usize] = {
             transform = Transform {
                 translation: Vec3::new(x, y, z+0.5),
                 rotation: Quat::from_rotation_x(std::f32::consts::PI / 2.0),
@@ -125,7 +159,7 @@ impl BlockBreaker {
             }).id()
         };
         //==========
-        breaking_textures[3] = {
+        breaking_textures[Face::West as usize] = {
             transform = Transform {
                 translation: Vec3::new(x-0.5, y, z),
                 rotation: Quat::from_rotation_x(-std::f32::consts::PI / 2.0),
@@ -141,7 +175,7 @@ impl BlockBreaker {
             }).id()
         };
         //==========
-        breaking_textures[4] = {
+        breaking_textures[Face::Down as usize] = {
             transform = Transform {
                 translation: Vec3::new(x, y-0.5, z),
                 rotation: Quat::from_rotation_x(std::f32::consts::PI),
@@ -156,7 +190,7 @@ impl BlockBreaker {
             }).id()
         };
         //==========
-        breaking_textures[5] = {
+        breaking_textures[Face::North as usize] = {
             transform = Transform {
                 translation: Vec3::new(x, y, z-0.5),
                 rotation: Quat::from_rotation_x(-std::f32::consts::PI / 2.0),
@@ -173,12 +207,9 @@ impl BlockBreaker {
         breaking_textures
     }
     
-    fn break_block(&self, commands: &mut Commands) {
-        for texture in self.block.textures {
-            commands.entity(texture).despawn();
-        }
+    fn break_block(&self, commands: &mut Commands, world: &mut VoxelWorld) {
+        world.set_block(self.block.coord.round().as_ivec3(), AIR);
         self.clean_up(commands);
-        commands.entity(self.block_id).despawn();
     }
     
     fn clean_up(&self, commands: &mut Commands) {
@@ -196,35 +227,15 @@ pub fn control_block(
     asset_server: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    mut world: ResMut<VoxelWorld>,
+    registry: Res<BlockRegistry>,
+    hotbar: Res<Hotbar>,
+    rules: Res<InteractionRules>,
     seen_block: Query<(Entity, &Block), With<SeenObject>>,
     mut player_status: Query<&mut PlayerStatus>
 ) {
     if let Ok((entity, block)) = seen_block.get_single() {
-        if mouse.pressed(MouseButton::Left) {
-            if let Some(mut breaker) = blockbreaker {
-                if breaker.block_id != entity {
-                    breaker.initialize(*block, entity)
-                } else {
-                    breaker.elapsed_time += time.delta_seconds();
-                    if breaker.elapsed_time > 0.375 {
-                        breaker.elapsed_time -= 0.375;
-                        breaker.phase += 1;
-                        if breaker.phase == 9 {
-                            breaker.break_block(&mut commands);
-                            commands.remove_resource::<BlockBreaker>();
-                        } else {
-                            breaker.update(&mut commands, &asset_server, &mut meshes, &mut materials)
-                        }
-                    }
-                }
-            } else {
-                let breaker = BlockBreaker::new(&mut commands, &asset_server, &mut meshes, &mut materials, *block, entity);
-                commands.insert_resource(breaker);
-            }
-        } else if let Some(breaker) = blockbreaker {
-            breaker.clean_up(&mut commands);
-            commands.remove_resource::<BlockBreaker>()
-        }
+        let config = rules.config();
         let mut status
             = match player_status.get_single_mut() {
             Ok(status) => status,
@@ -233,178 +244,208 @@ pub fn control_block(
                 return;
             }
         };
-        if mouse.pressed(MouseButton::Right) {
-            if !status.block_put {
-                let diff = (status.see_at - block.coord).to_array();
-                let mut new_coord = block.coord.to_array();
-                for dim in 0..3 {
-                    if decimal_round(diff[dim]) == 0.5 {
-                        new_coord[dim] += 1.0;
-                    } else if decimal_round(diff[dim]) == -0.5 {
-                        new_coord[dim] -= 1.0;
+        if let Some(break_time) = config.break_time {
+            if mouse.pressed(MouseButton::Left) {
+                if let Some(mut breaker) = blockbreaker {
+                    if breaker.block_id != entity {
+                        breaker.initialize(*block, entity)
+                    } else {
+                        let phase_time = break_time / BREAK_PHASES as f32;
+                        breaker.elapsed_time += time.delta_seconds();
+                        if breaker.elapsed_time > phase_time {
+                            breaker.elapsed_time -= phase_time;
+                            breaker.phase += 1;
+                            if breaker.phase == BREAK_PHASES {
+                                breaker.break_block(&mut commands, &mut world);
+                                commands.remove_resource::<BlockBreaker>();
+                            } else {
+                                breaker.update(&mut commands, &asset_server, &mut meshes, &mut materials)
+                            }
+                        }
                     }
+                } else {
+                    let breaker = BlockBreaker::new(&mut commands, &asset_server, &mut meshes, &mut materials, *block, entity);
+                    commands.insert_resource(breaker);
                 }
-                create_block(&mut commands, &asset_server, "textures/block/stone.png", &mut meshes, &mut materials, Vec3::from_array(new_coord));
-                status.block_put = true;
+            } else if let Some(breaker) = blockbreaker {
+                breaker.clean_up(&mut commands);
+                commands.remove_resource::<BlockBreaker>()
             }
         } else {
-            status.block_put = false;
+            // the game mode may have changed in the middle of breaking a block
+            if let Some(breaker) = blockbreaker {
+                breaker.clean_up(&mut commands);
+                commands.remove_resource::<BlockBreaker>()
+            }
+            if status.breaking.trigger(mouse.pressed(MouseButton::Left), config.repeat_delay, time.delta_seconds()) {
+                world.set_block(block.coord.round().as_ivec3(), AIR);
+            }
+        }
+        if status.placing.trigger(mouse.pressed(MouseButton::Right), config.repeat_delay, time.delta_seconds()) {
+            if registry.def(block.id).properties.contains(&Property::Open) {
+                // trapdoors are opened and closed instead of being built on
+                let properties = registry.properties(block.id);
+                let toggled = registry.with_properties(block.id, StateProperties { open: !properties.open, ..properties });
+                world.set_block(block.coord.round().as_ivec3(), toggled);
+            } else {
+                place_block(&mut world, &registry, &hotbar, &status, block);
+            }
         }
     }
 }
 
-pub fn create_block(
-    commands: &mut Commands,
-    asset_server: &Res<AssetServer>,
-    asset_path: &str,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-    coord: Vec3
+/// Places the selected block of the hotbar against the face of block the player looks at
+fn place_block(
+    world: &mut VoxelWorld,
+    registry: &BlockRegistry,
+    hotbar: &Hotbar,
+    status: &PlayerStatus,
+    block: &Block
 ) {
-    let (x, y, z) = (coord.x, coord.y, coord.z);
-    let mut textures: [Entity; 6] = [Entity::from_raw(0); 6];
-    let mut transform: Transform;
-    let mut material: Handle<StandardMaterial>;
-    let binding = asset_server.load(asset_path);
-    let texture_handles = [&binding; 6];
-    let plane = meshes.add(Mesh::from(shape::Plane{ size: 1.0 }));
-
-    textures[0] = {
-        transform = Transform {
-            translation: Vec3::new(x+0.5, y, z),
-            rotation: Quat::from_rotation_x(-std::f32::consts::PI / 2.0),
-            ..default()
-        };
-        transform.rotate(Quat::from_axis_angle(Vec3::Y, -std::f32::consts::PI / 2.0));
-
-        material = materials.add(StandardMaterial {
-            base_color_texture: Some(texture_handles[0].clone()),
-            alpha_mode: AlphaMode::Blend,
-            unlit: true,
-            ..default()
-        });
-
-        commands.spawn_bundle(PbrBundle {
-            mesh: plane.clone(),
-            material: material.clone(),
-            transform,
-            ..default()
-        })
-        .id()
-    };
-
-    textures[1] = {
-        material = materials.add(StandardMaterial {
-            base_color_texture: Some(texture_handles[1].clone()),
-            alpha_mode: AlphaMode::Blend,
-            unlit: true,
-            ..default()
-        });
-
-        commands.spawn_bundle(PbrBundle {
-            mesh: plane.clone(),
-            material: material.clone(),
-            transform: Transform::from_xyz(x, y+0.5, z),
-            ..default()
-        })
-        .id()
-    };
-
-    textures[2] = {
-        transform = Transform {
-            translation: Vec3::new(x, y, z+0.5),
-            rotation: Quat::from_rotation_x(std::f32::consts::PI / 2.0),
-            ..default()
-        };
-
-        material = materials.add(StandardMaterial {
-            base_color_texture: Some(texture_handles[2].clone()),
-            alpha_mode: AlphaMode::Blend,
-            unlit: true,
-            ..default()
-        });
-
-        commands.spawn_bundle(PbrBundle {
-            mesh: plane.clone(),
-            material: material.clone(),
-            transform,
-            ..default()
-        })
-        .id()
-    };
-
-    textures[3] = {
-        transform = Transform {
-            translation: Vec3::new(x-0.5, y, z),
-            rotation: Quat::from_rotation_x(-std::f32::consts::PI / 2.0),
-            ..default()
-        };
-        transform.rotate(Quat::from_axis_angle(Vec3::Y, std::f32::consts::PI / 2.0));
-
-        material = materials.add(StandardMaterial {
-            base_color_texture: Some(texture_handles[3].clone()),
-            alpha_mode: AlphaMode::Blend,
-            unlit: true,
-            ..default()
-        });
-
-        commands.spawn_bundle(PbrBundle {
-            mesh: plane.clone(),
-            material: material.clone(),
-            transform,
-            ..default()
-        })
-        .id()
+    // the new block goes next to the face which is looked at, wherever it is inside the block,
+    // unless the block looked at can be replaced
+    let normal = status.see_normal.round().as_ivec3();
+    let mut pos = block.coord.round().as_ivec3();
+    if !registry.def(block.id).replaceable {
+        pos += normal;
+    }
+    let (face, selected, target) = match (Face::from_normal(normal), hotbar.selected_block(), world.get_block_opt(pos)) {
+        (Some(face), Some(selected), Some(target)) => (face, selected, target),
+        _ => return
     };
+    if target != AIR && !registry.def(target).replaceable {
+        return;
+    }
+    let in_water = registry.default_state_of("water") == Some(target);
+    let properties = placement_properties(
+        registry.block_def(selected).shape,
+        face,
+        status.see_direction,
+        status.see_at.y - pos.y as f32,
+        in_water
+    );
+    world.set_block(pos, registry.state_id(selected, properties));
+}
 
-    textures[4] = {
-        transform = Transform {
-            translation: Vec3::new(x, y-0.5, z),
-            rotation: Quat::from_rotation_x(std::f32::consts::PI),
-            ..default()
-        };
-
-        material = materials.add(StandardMaterial {
-            base_color_texture: Some(texture_handles[4].clone()),
-            alpha_mode: AlphaMode::Blend,
-            unlit: true,
-            ..default()
-        });
+/// Whether any face of the block can be seen from a neighbour
+fn is_exposed(world: &VoxelWorld, registry: &BlockRegistry, pos: IVec3, id: StateId) -> bool {
+    if !registry.def(id).full {
+        return true;
+    }
+    NEIGHBOURS.iter().any(|offset| match world.get_block_opt(pos + *offset) {
+        Some(neighbour) => neighbour != id && registry.is_transparent(neighbour),
+        None => false
+    })
+}
 
-        commands.spawn_bundle(PbrBundle {
-            mesh: plane.clone(),
-            material: material.clone(),
-            transform,
-            ..default()
-        })
-        .id()
-    };
+/// Spawns or despawns the collider of the block at pos so that it matches the world
+pub fn refresh_block(
+    commands: &mut Commands,
+    entities: &mut BlockEntities,
+    world: &VoxelWorld,
+    registry: &BlockRegistry,
+    pos: IVec3
+) {
+    let id = world.get_block(pos);
+    let wanted = id != AIR && is_exposed(world, registry, pos, id);
+    let connections = connections(world, registry, pos);
+    if let Some((entity, current, current_connections)) = entities.0.get(&pos).copied() {
+        if wanted && current == id && current_connections == connections {
+            return;
+        }
+        commands.entity(entity).despawn();
+        entities.0.remove(&pos);
+    }
+    if wanted {
+        let entity = create_block(commands, registry, pos.as_vec3(), id, connections);
+        entities.0.insert(pos, (entity, id, connections));
+    }
+}
 
-    textures[5] = {
-        transform = Transform {
-            translation: Vec3::new(x, y, z-0.5),
-            rotation: Quat::from_rotation_x(-std::f32::consts::PI / 2.0),
-            ..default()
-        };
+/// The blocks which may need a collider once a freshly generated chunk is loaded, to be refreshed
+/// with refresh_block
+///
+/// Those are the blocks of the chunk exposed within it, its blocks along the borders, whose neighbours
+/// are not known yet, and the blocks of the chunks around along its borders, which it may expose or
+/// connect to. Only the chunk has to be in world, so this can run in a background job
+pub fn chunk_collider_candidates(world: &VoxelWorld, registry: &BlockRegistry, coord: IVec2) -> Vec<IVec3> {
+    let origin = IVec3::new(coord.x * CHUNK_SIZE, 0, coord.y * CHUNK_SIZE);
+    let mut candidates = Vec::new();
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_HEIGHT {
+            for z in 0..CHUNK_SIZE {
+                let pos = origin + IVec3::new(x, y, z);
+                let id = world.get_block(pos);
+                let border = x == 0 || z == 0 || x == CHUNK_SIZE - 1 || z == CHUNK_SIZE - 1;
+                if id != AIR && (border || is_exposed(world, registry, pos, id)) {
+                    candidates.push(pos);
+                }
+            }
+        }
+    }
+    for y in 0..CHUNK_HEIGHT {
+        for i in 0..CHUNK_SIZE {
+            candidates.extend([
+                IVec3::new(-1, y, i),
+                IVec3::new(CHUNK_SIZE, y, i),
+                IVec3::new(i, y, -1),
+                IVec3::new(i, y, CHUNK_SIZE),
+            ].map(|border| origin + border));
+        }
+    }
+    candidates
+}
 
-        material = materials.add(StandardMaterial {
-            base_color_texture: Some(texture_handles[5].clone()),
-            alpha_mode: AlphaMode::Blend,
-            unlit: true,
-            ..default()
-        });
+/// Despawns the colliders of an unloaded chunk
+pub fn despawn_chunk_blocks(commands: &mut Commands, entities: &mut BlockEntities, coord: IVec2) {
+    entities.0.retain(|pos, (entity, _, _)| {
+        let keep = VoxelWorld::chunk_coord(*pos) != coord;
+        if !keep {
+            commands.entity(*entity).despawn();
+        }
+        keep
+    });
+}
 
-        commands.spawn_bundle(PbrBundle {
-            mesh: plane.clone(),
-            material: material.clone(),
-            transform,
-            ..default()
-        })
-        .id()
-    };
+/// Keeps the block colliders in sync with the changes made to the world
+pub fn sync_block_entities(
+    mut commands: Commands,
+    mut events: EventReader<BlockChanged>,
+    mut entities: ResMut<BlockEntities>,
+    world: Res<VoxelWorld>,
+    registry: Res<BlockRegistry>
+) {
+    // a change can hide or reveal the blocks around it as well
+    let mut dirty = HashSet::new();
+    for change in events.iter() {
+        dirty.insert(change.pos);
+        dirty.extend(NEIGHBOURS.iter().map(|offset| change.pos + *offset));
+    }
+    for pos in dirty {
+        refresh_block(&mut commands, &mut entities, &world, &registry, pos);
+    }
+}
 
-    commands.spawn()
+/// Spawns the collider of a block following its shape
+///
+/// Blocks which can be walked through get a sensor, so that they can still be aimed at
+pub fn create_block(
+    commands: &mut Commands,
+    registry: &BlockRegistry,
+    coord: Vec3,
+    id: StateId,
+    connections: u8
+) -> Entity {
+    let shape = registry.def(id).shape;
+    let properties = registry.properties(id);
+    let mut entity = commands.spawn();
+    entity
         .insert_bundle(TransformBundle::from(Transform::from_translation(coord)))
-        .insert(Block{ textures, coord })
-        .insert(Collider::cuboid(0.5, 0.5, 0.5));
+        .insert(Block{ coord, id });
+    if let Some(collider) = model::collider(&shape.collision_boxes(properties, connections)) {
+        entity.insert(collider);
+    } else if let Some(collider) = model::collider(&shape.selection_boxes(properties, connections)) {
+        entity.insert(collider).insert(Sensor);
+    }
+    entity.id()
 }
\ No newline at end of file
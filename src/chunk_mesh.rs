@@ -0,0 +1,642 @@
+use bevy::{
+    prelude::*,
+    render::mesh::{
+        Indices,
+        PrimitiveTopology
+    },
+    tasks::{
+        AsyncComputeTaskPool,
+        Task
+    }
+};
+use futures_lite::future;
+
+use crate::{
+    atlas::BlockAtlas,
+    light::{
+        LightChannel,
+        MAX_LIGHT
+    },
+    model::{
+        connections,
+        BlockShape,
+        ModelBox
+    },
+    player::PlayerCamera,
+    registry::{
+        BlockRegistry,
+        RenderClass,
+        SharedRegistry,
+        StateId
+    },
+    sky::material::Atmosphere,
+    visibility::Connectivity,
+    world::{
+        BlockView,
+        Face,
+        VoxelWorld,
+        CHUNK_SIZE,
+        SECTION_COUNT
+    }
+};
+
+use std::collections::{HashMap, HashSet};
+
+/// Corners of each face, counter-clockwise seen from outside, in the order of Face
+const FACE_CORNERS: [[[f32; 3]; 4]; 6] = [
+    // +X
+    [[0.5, -0.5, 0.5], [0.5, -0.5, -0.5], [0.5, 0.5, -0.5], [0.5, 0.5, 0.5]],
+    // +Y
+    [[-0.5, 0.5, 0.5], [0.5, 0.5, 0.5], [0.5, 0.5, -0.5], [-0.5, 0.5, -0.5]],
+    // +Z
+    [[-0.5, -0.5, 0.5], [0.5, -0.5, 0.5], [0.5, 0.5, 0.5], [-0.5, 0.5, 0.5]],
+    // -X
+    [[-0.5, -0.5, -0.5], [-0.5, -0.5, 0.5], [-0.5, 0.5, 0.5], [-0.5, 0.5, -0.5]],
+    // -Y
+    [[-0.5, -0.5, -0.5], [0.5, -0.5, -0.5], [0.5, -0.5, 0.5], [-0.5, -0.5, 0.5]],
+    // -Z
+    [[0.5, -0.5, -0.5], [-0.5, -0.5, -0.5], [-0.5, 0.5, -0.5], [0.5, 0.5, -0.5]],
+];
+
+const FACE_UVS: [[f32; 2]; 4] = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+
+/// The two diagonal planes of a cross shaped block, each drawn from both sides
+const CROSS_QUADS: [[[f32; 3]; 4]; 4] = [
+    [[-0.5, -0.5, -0.5], [0.5, -0.5, 0.5], [0.5, 0.5, 0.5], [-0.5, 0.5, -0.5]],
+    [[0.5, -0.5, 0.5], [-0.5, -0.5, -0.5], [-0.5, 0.5, -0.5], [0.5, 0.5, 0.5]],
+    [[0.5, -0.5, -0.5], [-0.5, -0.5, 0.5], [-0.5, 0.5, 0.5], [0.5, 0.5, -0.5]],
+    [[-0.5, -0.5, 0.5], [0.5, -0.5, -0.5], [0.5, 0.5, -0.5], [-0.5, 0.5, 0.5]],
+];
+
+/// Brightness of a light level, every level is 80% as bright as the one above
+pub fn brightness(level: u8) -> f32 {
+    0.8f32.powi((MAX_LIGHT - level.min(MAX_LIGHT)) as i32)
+}
+
+/// Brightness of a face corner for each ambient occlusion level, 0 is a corner closed on both sides
+const AO_BRIGHTNESS: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+/// Ambient occlusion level of a face corner from the three blocks around it in front of the face
+fn corner_ao(side1: bool, side2: bool, corner: bool) -> usize {
+    if side1 && side2 {
+        0
+    } else {
+        3 - side1 as usize - side2 as usize - corner as usize
+    }
+}
+
+/// How many levels the sky light loses at the current position of the sun
+pub fn sky_darkness(sun_position: Vec3) -> u8 {
+    let height = sun_position.normalize_or_zero().y;
+    // full daylight a bit above the horizon, at night the sky keeps a little light
+    let daylight = ((height + 0.1) / 0.3).clamp(0.0, 1.0);
+    ((1.0 - daylight) * 11.0).round() as u8
+}
+
+/// Collects the faces of a mesh drawn with the block atlas
+#[derive(Default)]
+pub struct MeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+    face_centers: Vec<Vec3>,
+}
+
+/// The faces of a translucent section mesh, kept to draw them back to front
+#[derive(Component)]
+pub struct TranslucentFaces {
+    /// Center of each face, relative to the entity
+    centers: Vec<Vec3>,
+    /// The six indices of each face as they were built
+    indices: Vec<[u32; 6]>,
+    /// Block of the camera when the faces were last sorted
+    sorted_from: Option<IVec3>,
+}
+
+impl MeshBuilder {
+    fn add_face(&mut self, center: Vec3, face: Face, uv_rect: [Vec2; 2], shades: [f32; 4]) {
+        let corners = FACE_CORNERS[face as usize].map(|corner| center + Vec3::from_array(corner));
+        self.add_quad(corners, face.normal().as_vec3(), FACE_UVS.map(Vec2::from_array), uv_rect, shades);
+    }
+
+    /// Adds a face of a box of any size with the whole texture stretched over it
+    pub fn add_stretched_face(&mut self, min: Vec3, max: Vec3, face: Face, uv_rect: [Vec2; 2], shade: f32) {
+        let corners = FACE_CORNERS[face as usize].map(|corner| min + (Vec3::from_array(corner) + 0.5) * (max - min));
+        self.add_quad(corners, face.normal().as_vec3(), FACE_UVS.map(Vec2::from_array), uv_rect, [shade; 4]);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Adds a face of a box smaller than a block, its texture is cut the same way as the box
+    fn add_box_face(&mut self, center: Vec3, model_box: &ModelBox, face: Face, uv_rect: [Vec2; 2], shade: f32) {
+        let block_corners = FACE_CORNERS[face as usize].map(Vec3::from_array);
+        let size = model_box.max - model_box.min;
+        let corners = block_corners.map(|corner| model_box.min + (corner + 0.5) * size);
+        // the position of a corner along the edges of the block face gives its uv
+        let uvs = corners.map(|corner| Vec2::new(
+            (corner - block_corners[0]).dot(block_corners[1] - block_corners[0]),
+            1.0 - (corner - block_corners[0]).dot(block_corners[3] - block_corners[0]),
+        ));
+        self.add_quad(corners.map(|corner| center + corner), face.normal().as_vec3(), uvs, uv_rect, [shade; 4]);
+    }
+
+    /// Adds a quad whose corners are counter-clockwise seen from the front, uvs are within the texture of uv_rect
+    fn add_quad(&mut self, corners: [Vec3; 4], normal: Vec3, uvs: [Vec2; 4], uv_rect: [Vec2; 2], shades: [f32; 4]) {
+        let base = self.positions.len() as u32;
+        for ((corner, uv), shade) in corners.iter().zip(uvs).zip(shades) {
+            self.positions.push(corner.to_array());
+            self.normals.push(normal.to_array());
+            self.uvs.push((uv_rect[0] + (uv_rect[1] - uv_rect[0]) * uv).to_array());
+            self.colors.push([shade, shade, shade, 1.0]);
+        }
+        self.face_centers.push((corners[0] + corners[2]) / 2.0);
+        // split the quad along its brighter diagonal, otherwise the interpolation
+        // makes the shading depend on the orientation of the face
+        if shades[0] + shades[2] >= shades[1] + shades[3] {
+            self.indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+        } else {
+            self.indices.extend([base + 1, base + 2, base + 3, base + 1, base + 3, base]);
+        }
+    }
+
+    fn translucent_faces(&self) -> TranslucentFaces {
+        TranslucentFaces {
+            centers: self.face_centers.clone(),
+            indices: self.indices
+                .chunks_exact(6)
+                .map(|face| [face[0], face[1], face[2], face[3], face[4], face[5]])
+                .collect(),
+            sorted_from: None,
+        }
+    }
+
+    pub fn build(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+        mesh
+    }
+}
+
+fn is_occluder(world: &impl BlockView, registry: &BlockRegistry, pos: IVec3) -> bool {
+    world.get_block_opt(pos).map_or(false, |id| !registry.is_transparent(id))
+}
+
+// the light level a block receives at the current time of day
+fn light_level(world: &impl BlockView, pos: IVec3, sky_darkness: u8) -> u8 {
+    let sky = world.light(pos, LightChannel::Sky).unwrap_or(0);
+    let block = world.light(pos, LightChannel::Block).unwrap_or(0);
+    sky.saturating_sub(sky_darkness).max(block)
+}
+
+/// Shade of each corner of a face, the light of the blocks in front of the corner
+/// is averaged and darkened by their ambient occlusion
+fn face_shades(
+    world: &impl BlockView,
+    registry: &BlockRegistry,
+    pos: IVec3,
+    face: Face,
+    sky_darkness: u8
+) -> [f32; 4] {
+    let normal = face.normal();
+    let front = pos + normal;
+    let front_brightness = brightness(light_level(world, front, sky_darkness));
+    FACE_CORNERS[face as usize].map(|corner| {
+        // the two directions along the face which point towards the corner
+        let mut tangents = [[0; 3]; 2];
+        let mut count = 0;
+        for axis in 0..3 {
+            if normal.to_array()[axis] == 0 {
+                tangents[count][axis] = if corner[axis] > 0.0 { 1 } else { -1 };
+                count += 1;
+            }
+        }
+        let side1 = front + IVec3::from_array(tangents[0]);
+        let side2 = front + IVec3::from_array(tangents[1]);
+        let diagonal = side1 + IVec3::from_array(tangents[1]);
+        let occluded = [side1, side2, diagonal].map(|pos| is_occluder(world, registry, pos));
+        let ao = corner_ao(occluded[0], occluded[1], occluded[2]);
+
+        let mut total = front_brightness;
+        let mut samples = 1.0;
+        for (sample, occluded) in [side1, side2, diagonal].iter().zip(occluded) {
+            // light cannot reach the diagonal block around two occluding sides
+            if !occluded && !(ao == 0 && *sample == diagonal) {
+                total += brightness(light_level(world, *sample, sky_darkness));
+                samples += 1.0;
+            }
+        }
+        total / samples * AO_BRIGHTNESS[ao]
+    })
+}
+
+/// Adds the geometry of a block which is not a full cube, partial faces are lit by the block itself
+fn add_model(
+    builder: &mut MeshBuilder,
+    world: &impl BlockView,
+    registry: &BlockRegistry,
+    pos: IVec3,
+    center: Vec3,
+    sky_darkness: u8
+) {
+    let id = world.get_block(pos);
+    let shape = registry.def(id).shape;
+    let own_shade = brightness(light_level(world, pos, sky_darkness));
+    if shape == BlockShape::Cross {
+        let uv_rect = registry.face_uv(id, Face::South);
+        for quad in CROSS_QUADS {
+            let corners = quad.map(|corner| center + Vec3::from_array(corner));
+            let normal = (corners[1] - corners[0]).cross(corners[3] - corners[0]).normalize();
+            builder.add_quad(corners, normal, FACE_UVS.map(Vec2::from_array), uv_rect, [own_shade; 4]);
+        }
+        return;
+    }
+    for model_box in shape.boxes(registry.properties(id), connections(world, registry, pos)) {
+        for face in Face::ALL {
+            let shade = if model_box.touches(face) {
+                // on the side of the block the face is hidden by an opaque neighbour
+                let neighbour = pos + face.normal();
+                match world.get_block_opt(neighbour) {
+                    Some(other) if registry.is_transparent(other) => {},
+                    _ => continue
+                }
+                brightness(light_level(world, neighbour, sky_darkness))
+            } else {
+                own_shade
+            };
+            builder.add_box_face(center, &model_box, face, registry.face_uv(id, face), shade);
+        }
+    }
+}
+
+/// Adds the faces of a full block drawn as id at pos which are not hidden by their neighbour
+fn add_cube(
+    builder: &mut MeshBuilder,
+    world: &impl BlockView,
+    registry: &BlockRegistry,
+    pos: IVec3,
+    id: StateId,
+    center: Vec3,
+    sky_darkness: u8
+) {
+    for face in Face::ALL {
+        let neighbour = pos + face.normal();
+        // faces against an identical see-through block, like water in water, are culled
+        match world.get_block_opt(neighbour) {
+            Some(other) if other == id => continue,
+            Some(other) if registry.properties(other).waterlogged
+                && registry.default_state_of("water") == Some(id) => continue,
+            Some(other) if registry.is_transparent(other) => {},
+            _ => continue
+        }
+        let shades = face_shades(world, registry, pos, face, sky_darkness);
+        builder.add_face(center, face, registry.face_uv(id, face), shades);
+    }
+}
+
+/// Mesh of a single unshaded block centred on the origin, for blocks drawn outside of the chunks
+pub fn block_mesh(registry: &BlockRegistry, id: StateId) -> Mesh {
+    let mut builder = MeshBuilder::default();
+    for face in Face::ALL {
+        builder.add_face(Vec3::ZERO, face, registry.face_uv(id, face), [1.0; 4]);
+    }
+    builder.build()
+}
+
+/// Center of a section in world space, the section meshes are placed there so that
+/// the renderer sorts translucent sections by their actual distance
+fn section_center(section: IVec3) -> Vec3 {
+    (section * CHUNK_SIZE).as_vec3() + Vec3::splat((CHUNK_SIZE - 1) as f32 / 2.0)
+}
+
+/// Builds the mesh builders of a 16x16x16 section, one per render class, with positions relative to the section center
+fn build_section(
+    world: &impl BlockView,
+    registry: &BlockRegistry,
+    section: IVec3,
+    sky_darkness: u8
+) -> HashMap<RenderClass, MeshBuilder> {
+    let origin = section * CHUNK_SIZE;
+    let center = section_center(section);
+    let mut builders: HashMap<RenderClass, MeshBuilder> = HashMap::new();
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let local = IVec3::new(x, y, z);
+                let pos = origin + local;
+                let id = world.get_block(pos);
+                if !registry.def(id).is_drawn() {
+                    continue;
+                }
+                let builder = builders.entry(registry.render_class(id)).or_default();
+                if registry.def(id).shape == BlockShape::Cube {
+                    add_cube(builder, world, registry, pos, id, pos.as_vec3() - center, sky_darkness);
+                    continue;
+                }
+                add_model(builder, world, registry, pos, pos.as_vec3() - center, sky_darkness);
+                // a waterlogged block stands in a block of water
+                if let Some(water) = registry.default_state_of("water").filter(|_| registry.properties(id).waterlogged) {
+                    let builder = builders.entry(registry.render_class(water)).or_default();
+                    add_cube(builder, world, registry, pos, water, pos.as_vec3() - center, sky_darkness);
+                }
+            }
+        }
+    }
+    builders
+}
+
+/// Width of the copy of a section a mesh job works on, the section and the blocks touching it
+const VIEW_SIZE: i32 = CHUNK_SIZE + 2;
+
+/// Copy of a section and of the blocks around it, everything its mesh depends on
+struct SectionView {
+    /// World position of the first block of the copy
+    origin: IVec3,
+    blocks: Vec<Option<StateId>>,
+    /// Both light channels packed like in the chunks
+    light: Vec<u8>,
+}
+
+impl SectionView {
+    fn capture(world: &VoxelWorld, section: IVec3) -> Self {
+        let origin = section * CHUNK_SIZE - IVec3::ONE;
+        let volume = (VIEW_SIZE * VIEW_SIZE * VIEW_SIZE) as usize;
+        let mut blocks = Vec::with_capacity(volume);
+        let mut light = Vec::with_capacity(volume);
+        for y in 0..VIEW_SIZE {
+            for z in 0..VIEW_SIZE {
+                for x in 0..VIEW_SIZE {
+                    let pos = origin + IVec3::new(x, y, z);
+                    blocks.push(world.get_block_opt(pos));
+                    let sky = world.light(pos, LightChannel::Sky).unwrap_or(0);
+                    let block = world.light(pos, LightChannel::Block).unwrap_or(0);
+                    light.push(LightChannel::Block.pack(LightChannel::Sky.pack(0, sky), block));
+                }
+            }
+        }
+        Self { origin, blocks, light }
+    }
+
+    fn index(&self, pos: IVec3) -> Option<usize> {
+        let local = pos - self.origin;
+        if local.min_element() < 0 || local.max_element() >= VIEW_SIZE {
+            return None;
+        }
+        Some(((local.y * VIEW_SIZE + local.z) * VIEW_SIZE + local.x) as usize)
+    }
+}
+
+impl BlockView for SectionView {
+    fn get_block_opt(&self, pos: IVec3) -> Option<StateId> {
+        self.index(pos).and_then(|index| self.blocks[index])
+    }
+
+    fn light(&self, pos: IVec3, channel: LightChannel) -> Option<u8> {
+        let index = self.index(pos)?;
+        self.blocks[index].map(|_| channel.unpack(self.light[index]))
+    }
+}
+
+/// At most this many sections are meshed at the same time
+const MAX_MESH_JOBS: usize = 8;
+/// At most this many finished meshes are uploaded per frame, so that a burst of work does not stall a frame
+const MAX_MESH_UPLOADS: usize = 4;
+
+type MeshJob = Task<(HashMap<RenderClass, MeshBuilder>, Connectivity)>;
+
+/// The mesh entities drawing each section of the world, and the sections being meshed in the background
+#[derive(Default)]
+pub struct ChunkMeshes {
+    sections: HashMap<IVec3, Vec<Entity>>,
+    /// How the faces of the meshed sections see each other, the others count as open
+    connectivity: HashMap<IVec3, Connectivity>,
+    dirty: HashSet<IVec3>,
+    jobs: HashMap<IVec3, MeshJob>,
+    /// Loaded chunks some sections of which have not been meshed yet
+    unmeshed: HashSet<IVec2>,
+    sky_darkness: u8,
+}
+
+impl ChunkMeshes {
+    /// Queues the section for a rebuild
+    pub fn mark_section(&mut self, section: IVec3) {
+        if section.y >= 0 && section.y < SECTION_COUNT {
+            self.dirty.insert(section);
+        }
+    }
+
+    /// Queues the section of the block, and the sections around it when the block is on their border
+    pub fn mark_block(&mut self, pos: IVec3) {
+        // the shading of a face depends on the blocks touching its corners
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    self.mark_section(VoxelWorld::section_coord(pos + IVec3::new(x, y, z)));
+                }
+            }
+        }
+    }
+
+    /// Queues every section of a chunk
+    pub fn mark_chunk(&mut self, coord: IVec2) {
+        for y in 0..SECTION_COUNT {
+            self.mark_section(IVec3::new(coord.x, y, coord.y));
+        }
+    }
+
+    /// Queues every section of a chunk which was just loaded
+    pub fn add_chunk(&mut self, coord: IVec2) {
+        self.unmeshed.insert(coord);
+        self.mark_chunk(coord);
+    }
+
+    /// Whether the sections of a chunk have all been meshed since it was loaded
+    pub fn is_chunk_meshed(&self, coord: IVec2) -> bool {
+        !self.unmeshed.contains(&coord)
+    }
+
+    fn has_pending_sections(&self, coord: IVec2) -> bool {
+        (0..SECTION_COUNT).any(|y| {
+            let section = IVec3::new(coord.x, y, coord.y);
+            self.dirty.contains(&section) || self.jobs.contains_key(&section)
+        })
+    }
+
+    /// Despawns the meshes of an unloaded chunk, dropping its jobs cancels them
+    pub fn remove_chunk(&mut self, commands: &mut Commands, coord: IVec2) {
+        self.unmeshed.remove(&coord);
+        for y in 0..SECTION_COUNT {
+            let section = IVec3::new(coord.x, y, coord.y);
+            self.dirty.remove(&section);
+            self.jobs.remove(&section);
+            self.connectivity.remove(&section);
+            self.despawn_section(commands, section);
+        }
+    }
+
+    pub fn connectivity(&self, section: IVec3) -> Connectivity {
+        self.connectivity.get(&section).copied().unwrap_or(Connectivity::ALL)
+    }
+
+    /// The mesh entities of every drawn section
+    pub fn section_entities(&self) -> impl Iterator<Item = (IVec3, &[Entity])> {
+        self.sections.iter().map(|(section, entities)| (*section, entities.as_slice()))
+    }
+
+    fn despawn_section(&mut self, commands: &mut Commands, section: IVec3) {
+        for entity in self.sections.remove(&section).unwrap_or_default() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Starts background jobs for the queued sections, nearest to the camera first,
+/// and for all sections when the daylight changes
+pub fn queue_section_meshes(
+    mut commands: Commands,
+    mut chunk_meshes: ResMut<ChunkMeshes>,
+    world: Res<VoxelWorld>,
+    registry: Option<Res<SharedRegistry>>,
+    atmosphere: Res<Atmosphere>,
+    atlas: Option<Res<BlockAtlas>>,
+    camera: Query<&GlobalTransform, With<PlayerCamera>>,
+) {
+    // nothing can be drawn before the block textures are packed
+    let registry = match (registry, atlas) {
+        (Some(registry), Some(_)) => registry,
+        _ => return,
+    };
+    let eye = camera.get_single().map_or(Vec3::ZERO, |transform| transform.translation());
+    let darkness = sky_darkness(atmosphere.sun_position);
+    if darkness != chunk_meshes.sky_darkness {
+        chunk_meshes.sky_darkness = darkness;
+        let drawn: Vec<IVec3> = chunk_meshes.sections.keys().copied().collect();
+        chunk_meshes.dirty.extend(drawn);
+    }
+
+    // a section which is still being meshed waits for its job before it is meshed again
+    let mut dirty: Vec<IVec3> = chunk_meshes.dirty.iter()
+        .filter(|section| !chunk_meshes.jobs.contains_key(section))
+        .copied()
+        .collect();
+    dirty.sort_by(|a, b| {
+        section_center(*a).distance_squared(eye).total_cmp(&section_center(*b).distance_squared(eye))
+    });
+    for section in dirty {
+        if chunk_meshes.jobs.len() >= MAX_MESH_JOBS {
+            break;
+        }
+        chunk_meshes.dirty.remove(&section);
+        // empty sections are common and need no job
+        if world.is_section_empty(section) {
+            chunk_meshes.despawn_section(&mut commands, section);
+            chunk_meshes.connectivity.remove(&section);
+            continue;
+        }
+        let view = SectionView::capture(&world, section);
+        let registry = registry.0.clone();
+        let job = AsyncComputeTaskPool::get().spawn(async move {
+            let origin = section * CHUNK_SIZE;
+            // see-through blocks do not hide what is behind them
+            let connectivity = Connectivity::compute(|local| {
+                view.get_block_opt(origin + local).map_or(true, |id| registry.is_transparent(id))
+            });
+            (build_section(&view, &registry, section, darkness), connectivity)
+        });
+        chunk_meshes.jobs.insert(section, job);
+    }
+}
+
+/// Replaces the meshes of the sections whose job is done
+pub fn apply_section_meshes(
+    mut commands: Commands,
+    mut chunk_meshes: ResMut<ChunkMeshes>,
+    atlas: Option<Res<BlockAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let atlas = match atlas {
+        Some(atlas) => atlas,
+        None => return,
+    };
+    let mut finished = Vec::new();
+    for (section, job) in chunk_meshes.jobs.iter_mut() {
+        if finished.len() >= MAX_MESH_UPLOADS {
+            break;
+        }
+        if let Some(result) = future::block_on(future::poll_once(job)) {
+            finished.push((*section, result));
+        }
+    }
+    for (section, (builders, connectivity)) in finished {
+        chunk_meshes.jobs.remove(&section);
+        chunk_meshes.connectivity.insert(section, connectivity);
+        chunk_meshes.despawn_section(&mut commands, section);
+        let transform = Transform::from_translation(section_center(section));
+        let mut entities = Vec::new();
+        for (class, builder) in builders {
+            if builder.is_empty() {
+                continue;
+            }
+            let translucent = (class == RenderClass::Translucent).then(|| builder.translucent_faces());
+            let mut entity = commands.spawn_bundle(MaterialMeshBundle {
+                mesh: meshes.add(builder.build()),
+                material: atlas.material(class),
+                transform,
+                ..default()
+            });
+            if let Some(faces) = translucent {
+                entity.insert(faces);
+            }
+            entities.push(entity.id());
+        }
+        if !entities.is_empty() {
+            chunk_meshes.sections.insert(section, entities);
+        }
+    }
+    let meshed: Vec<IVec2> = chunk_meshes.unmeshed.iter()
+        .filter(|coord| !chunk_meshes.has_pending_sections(**coord))
+        .copied()
+        .collect();
+    for coord in meshed {
+        chunk_meshes.unmeshed.remove(&coord);
+    }
+}
+
+/// Orders the faces of the translucent section meshes from the farthest to the nearest
+/// whenever the camera enters another block, blending is only correct in that order
+pub fn sort_translucent_faces(
+    camera: Query<&GlobalTransform, With<PlayerCamera>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut sections: Query<(&GlobalTransform, &Handle<Mesh>, &mut TranslucentFaces)>,
+) {
+    let eye = match camera.get_single() {
+        Ok(transform) => transform.translation(),
+        _ => return,
+    };
+    let eye_block = eye.floor().as_ivec3();
+    for (transform, handle, mut faces) in &mut sections {
+        if faces.sorted_from == Some(eye_block) {
+            continue;
+        }
+        let mesh = match meshes.get_mut(handle) {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+        let local_eye = eye - transform.translation();
+        let mut order: Vec<usize> = (0..faces.centers.len()).collect();
+        order.sort_by(|a, b| {
+            let distance_a = faces.centers[*a].distance_squared(local_eye);
+            let distance_b = faces.centers[*b].distance_squared(local_eye);
+            distance_b.total_cmp(&distance_a)
+        });
+        mesh.set_indices(Some(Indices::U32(order.iter().flat_map(|face| faces.indices[*face]).collect())));
+        faces.sorted_from = Some(eye_block);
+    }
+}
\ No newline at end of file
@@ -4,7 +4,11 @@ use bevy::{
     ui::entity::TextBundle
 };
 
-use crate::player::Player;
+use crate::{
+    player::Player,
+    world::VoxelWorld,
+    world_time::WorldTime
+};
 
 #[derive(Default)]
 pub struct Debugger {
@@ -43,6 +47,8 @@ pub fn update_debugger(
     mut debugger: ResMut<Debugger>,
     keyboard_input: Res<Input<KeyCode>>,
     player: Query<&Transform, With<Player>>,
+    world: Res<VoxelWorld>,
+    time: Res<WorldTime>,
     mut query: Query<&mut Text>,
 ) {
     if keyboard_input.just_pressed(KeyCode::F3) {
@@ -63,8 +69,14 @@ pub fn update_debugger(
                 return;
             }
         };
+        // the palette sections compared with one u32 per block
+        let (packed, raw) = world.block_memory();
         for mut text in &mut query {
-            text.sections[0].value = format!("XYZ: {} / {} / {}", translation.x, translation.y, translation.z)
+            text.sections[0].value = format!(
+                "XYZ: {} / {} / {}\nBlocks: {} KiB ({} KiB unpacked)\nDay {}, tick {} / {}{}",
+                translation.x, translation.y, translation.z, packed / 1024, raw / 1024,
+                time.day(), time.time_of_day(), time.day_length, if time.frozen { " (frozen)" } else { "" }
+            )
         }
     }
 }
\ No newline at end of file
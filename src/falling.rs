@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    atlas::BlockAtlas,
+    chunk_mesh::block_mesh,
+    registry::{
+        BlockRegistry,
+        StateId,
+        AIR
+    },
+    world::VoxelWorld
+};
+
+/// A block which lost its support and is falling as a physical body
+#[derive(Component)]
+pub struct FallingBlock {
+    pub id: StateId,
+    age: f32
+}
+
+impl FallingBlock {
+    pub fn new(id: StateId) -> Self {
+        Self { id, age: 0.0 }
+    }
+}
+
+/// A block lying on the ground as an item
+#[derive(Component)]
+pub struct DroppedItem {
+    pub id: StateId
+}
+
+/// Spawns a falling block in place of the block at pos, the block itself has to be removed by the caller
+pub fn spawn_falling_block(commands: &mut Commands, pos: IVec3, id: StateId) {
+    commands.spawn()
+        .insert(FallingBlock::new(id))
+        .insert_bundle(TransformBundle::from(Transform::from_translation(pos.as_vec3())));
+}
+
+/// Gives the newly spawned falling blocks their mesh and rigid body
+pub fn setup_falling_blocks(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    atlas: Option<Res<BlockAtlas>>,
+    registry: Res<BlockRegistry>,
+    falling_blocks: Query<(Entity, &FallingBlock), Added<FallingBlock>>
+) {
+    let atlas = match atlas {
+        Some(atlas) => atlas,
+        None => return
+    };
+    for (entity, falling) in &falling_blocks {
+        commands.entity(entity)
+            .insert(meshes.add(block_mesh(&registry, falling.id)))
+            .insert(atlas.material(registry.render_class(falling.id)))
+            .insert_bundle(VisibilityBundle::default())
+            .insert(RigidBody::Dynamic)
+            // slightly smaller than a block so that it does not catch on the walls while falling
+            .insert(Collider::cuboid(0.49, 0.49, 0.49))
+            .insert(
+                LockedAxes::ROTATION_LOCKED
+                | LockedAxes::TRANSLATION_LOCKED_X
+                | LockedAxes::TRANSLATION_LOCKED_Z
+            )
+            .insert(Velocity::zero());
+    }
+}
+
+/// Turns the falling blocks which came to rest back into blocks, or into items
+pub fn land_falling_blocks(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    atlas: Option<Res<BlockAtlas>>,
+    mut world: ResMut<VoxelWorld>,
+    registry: Res<BlockRegistry>,
+    mut falling_blocks: Query<(Entity, &Transform, &Velocity, &mut FallingBlock)>
+) {
+    let atlas = match atlas {
+        Some(atlas) => atlas,
+        None => return
+    };
+    let positions: Vec<(Entity, Vec3)> = falling_blocks.iter()
+        .map(|(entity, transform, _, _)| (entity, transform.translation))
+        .collect();
+    for (entity, transform, velocity, mut falling) in &mut falling_blocks {
+        falling.age += time.delta_seconds();
+        if transform.translation.y < -64.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        if falling.age < 0.1 || velocity.linvel.y.abs() > 0.01 {
+            continue;
+        }
+        let pos = transform.translation.round().as_ivec3();
+        let on_falling_block = positions.iter().any(|(other, other_pos)| {
+            let offset = transform.translation - *other_pos;
+            *other != entity && offset.x.abs() < 0.5 && offset.z.abs() < 0.5 && (0.5..1.5).contains(&offset.y)
+        });
+        let support = match world.get_block_opt(pos - IVec3::Y) {
+            // resting on another falling block, wait until that one has landed
+            Some(AIR) if on_falling_block => continue,
+            // resting on something else than a block, like an item, it can not land there
+            Some(AIR) => false,
+            Some(below) => registry.def(below).full,
+            None => false
+        };
+        commands.entity(entity).despawn();
+        if support && world.get_block_opt(pos) == Some(AIR) {
+            world.set_block(pos, falling.id);
+        } else {
+            let mesh = meshes.add(block_mesh(&registry, falling.id));
+            let material = atlas.material(registry.render_class(falling.id));
+            commands.spawn_bundle(TransformBundle::from(Transform::from_translation(transform.translation)))
+                .insert_bundle(VisibilityBundle::default())
+                .insert(DroppedItem { id: falling.id })
+                .insert(RigidBody::Dynamic)
+                .insert(Collider::cuboid(0.125, 0.125, 0.125))
+                .with_children(|parent| {
+                    parent.spawn_bundle(MaterialMeshBundle {
+                        mesh,
+                        material,
+                        transform: Transform::from_scale(Vec3::splat(0.25)),
+                        ..default()
+                    });
+                });
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,63 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::ShaderType
+};
+
+use crate::{
+    loader::ChunkLoader,
+    lod::LodSettings,
+    player::PlayerCamera,
+    registry::BlockRegistry,
+    weather::Weather,
+    world::{
+        VoxelWorld,
+        CHUNK_SIZE
+    }
+};
+
+/// How the terrain fades into the sky with the distance from the camera
+#[derive(ShaderType, Clone, Copy, PartialEq, Debug, Default)]
+pub struct Fog {
+    /// Distance where the fog starts
+    pub start: f32,
+    /// Distance where the fog hides everything
+    pub end: f32,
+    /// Colour mixed into the colour of the sky, as much as its alpha
+    pub tint: Vec4,
+}
+
+/// Ties the fog to the render distance and thickens it underwater and in the rain
+pub fn update_fog(
+    mut fog: ResMut<Fog>,
+    loader: Res<ChunkLoader>,
+    lod_settings: Res<LodSettings>,
+    weather: Res<Weather>,
+    world: Res<VoxelWorld>,
+    registry: Res<BlockRegistry>,
+    camera: Query<&GlobalTransform, With<PlayerCamera>>,
+) {
+    let eye = match camera.get_single() {
+        Ok(transform) => transform.translation().round().as_ivec3(),
+        _ => return,
+    };
+    let underwater = registry.default_state_of("water") == world.get_block_opt(eye);
+    let wanted = if underwater {
+        Fog {
+            start: 0.0,
+            end: 24.0,
+            tint: Vec4::new(0.1, 0.25, 0.5, 0.85),
+        }
+    } else {
+        // the distant terrain is drawn farther than the chunks are loaded
+        let render_distance = (loader.view_distance.max(lod_settings.max_distance()) * CHUNK_SIZE) as f32;
+        let clearness = 1.0 - 0.7 * weather.rain;
+        Fog {
+            start: render_distance * 0.5 * clearness,
+            end: render_distance * clearness,
+            tint: Vec4::new(0.5, 0.52, 0.55, 0.5 * weather.rain),
+        }
+    };
+    if *fog != wanted {
+        *fog = wanted;
+    }
+}
\ No newline at end of file
@@ -1,17 +1,103 @@
 use noise::{NoiseFn, Perlin};
 use ndarray::*;
 
-pub fn height_generator(x: i32, z: i32) -> ArrayBase<OwnedRepr<u32>, Dim<[usize; 3]>> {
+use bevy::prelude::*;
+
+use crate::{
+    light::light_new_chunk,
+    registry::{
+        BlockRegistry,
+        StateId,
+        AIR
+    },
+    utils::Rng,
+    world::{
+        Chunk,
+        CHUNK_HEIGHT,
+        CHUNK_SIZE
+    }
+};
+
+/// Top of the water, the generator fills the terrain below it with water
+pub const SEA_LEVEL: i32 = 62;
+
+// height of the terrain around y = 63 at a column
+fn terrain_offset(perlin: &Perlin, x: i32, z: i32) -> i32 {
+    (perlin.get([x as f64 / 100.0, z as f64 / 100.0])*10.0).round() as i32
+}
+
+/// Below this temperature precipitation falls as snow
+pub const SNOW_TEMPERATURE: f32 = 0.15;
+
+/// How warm the climate is at a position, around 0.6 at sea level and colder with the height
+///
+/// There are no biomes yet, the temperature only decides between rain and snow
+pub fn temperature(perlin: &Perlin, x: i32, y: i32, z: i32) -> f32 {
+    let climate = perlin.get([x as f64 / 600.0 + 100.5, z as f64 / 600.0 - 50.5]) as f32;
+    0.6 + climate * 0.8 - (y - SEA_LEVEL) as f32 * 0.02
+}
+
+/// The topmost block of a column and its height, as height_generator places it
+///
+/// The distant terrain is drawn from this, so it matches the chunks once they load
+pub fn surface(perlin: &Perlin, registry: &BlockRegistry, x: i32, z: i32) -> (i32, StateId) {
+    let state = |name: &str| registry.default_state_of(name).unwrap_or(AIR);
+    let y = terrain_offset(perlin, x, z);
+    if 63 + y < SEA_LEVEL {
+        (SEA_LEVEL, state("water"))
+    } else if y <= 1 {
+        (63 + y, state("sand"))
+    } else {
+        (63 + y, state("grass"))
+    }
+}
+
+/// Generates, decorates and lights a whole chunk, this runs in a background job
+pub fn generate_chunk(registry: &BlockRegistry, seed: u64, coord: IVec2) -> Chunk {
+    let mut chunk = Chunk::from(height_generator(registry, coord.x * CHUNK_SIZE, coord.y * CHUNK_SIZE));
+    decorate(&mut chunk, registry, seed, coord);
+    light_new_chunk(chunk, registry, coord)
+}
+
+/// Scatters tall grass over the grass of a chunk, the same seed always decorates a chunk the same way
+fn decorate(chunk: &mut Chunk, registry: &BlockRegistry, seed: u64, coord: IVec2) {
+    let (grass, tall_grass) = match (registry.default_state_of("grass"), registry.default_state_of("tall_grass")) {
+        (Some(grass), Some(tall_grass)) => (grass, tall_grass),
+        _ => return,
+    };
+    let mut rng = Rng::new(seed ^ ((coord.x as u32 as u64) << 32 | coord.y as u32 as u64));
+    for x in 0..CHUNK_SIZE as usize {
+        for z in 0..CHUNK_SIZE as usize {
+            let top = (0..CHUNK_HEIGHT as usize - 1).rev().find(|y| chunk.block([x, *y, z]) != AIR);
+            if let Some(y) = top {
+                if chunk.block([x, y, z]) == grass && rng.next_below(8) == 0 {
+                    chunk.set_block([x, y + 1, z], tall_grass);
+                }
+            }
+        }
+    }
+}
+
+pub fn height_generator(registry: &BlockRegistry, x: i32, z: i32) -> ArrayBase<OwnedRepr<u32>, Dim<[usize; 3]>> {
     let perlin = Perlin::new();
+    let state = |name: &str| registry.default_state_of(name).unwrap_or(AIR);
+    let (water, rock, sand, dirt, grass) = (state("water"), state("rock"), state("sand"), state("dirt"), state("grass"));
 
-    let mut height_map: Array3<u32> = Array::ones((16, 63, 16));
+    let mut height_map: Array3<u32> = Array::from_elem((16, 63, 16), water);
     let air = ArrayView::from(&[0; 49408]).into_shape((16, 193, 16)).unwrap();
     height_map.append(Axis(1), air);
     let mut y: i32;
     for i in x..x+16 {
         for j in z..z+16 {
-            y = (perlin.get([i as f64 / 100.0, j as f64 / 100.0])*10.0).round() as i32;
-            height_map.slice_mut(s![i-x, 0..64+y, j-z]).fill(2);
+            y = terrain_offset(&perlin, i, j);
+            height_map.slice_mut(s![i-x, 0..64+y, j-z]).fill(rock);
+            // the ground around the water is covered with sand, the rest with grass over dirt
+            if y <= 1 {
+                height_map[[(i-x) as usize, (63+y) as usize, (j-z) as usize]] = sand;
+            } else {
+                height_map.slice_mut(s![i-x, 60+y..63+y, j-z]).fill(dirt);
+                height_map[[(i-x) as usize, (63+y) as usize, (j-z) as usize]] = grass;
+            }
         }
     }
     return height_map
@@ -0,0 +1,88 @@
+use bevy::{
+    input::mouse::MouseWheel,
+    prelude::*
+};
+
+use crate::{
+    block::Block,
+    player::SeenObject,
+    registry::{
+        BlockId,
+        BlockRegistry
+    }
+};
+
+pub const HOTBAR_SIZE: usize = 9;
+
+/// Blocks the hotbar starts with, by name
+const DEFAULT_BLOCKS: [&str; HOTBAR_SIZE] = [
+    "rock", "dirt", "log", "glass", "stone_slab", "stone_stairs", "fence", "trapdoor", "lamp"
+];
+
+/// The blocks the player can place, one slot is selected at a time
+pub struct Hotbar {
+    pub slots: [Option<BlockId>; HOTBAR_SIZE],
+    pub selected: usize,
+}
+
+impl Hotbar {
+    pub fn selected_block(&self) -> Option<BlockId> {
+        self.slots[self.selected]
+    }
+
+    /// Selects the slot holding block, or puts it into the selected slot when no slot holds it
+    pub fn pick(&mut self, block: BlockId) {
+        match self.slots.iter().position(|slot| *slot == Some(block)) {
+            Some(index) => self.selected = index,
+            None => self.slots[self.selected] = Some(block),
+        }
+    }
+}
+
+impl FromWorld for Hotbar {
+    fn from_world(world: &mut World) -> Self {
+        let registry = world.resource::<BlockRegistry>();
+        Self {
+            slots: DEFAULT_BLOCKS.map(|name| registry.id(name)),
+            selected: 0,
+        }
+    }
+}
+
+const SLOT_KEYS: [KeyCode; HOTBAR_SIZE] = [
+    KeyCode::Key1, KeyCode::Key2, KeyCode::Key3,
+    KeyCode::Key4, KeyCode::Key5, KeyCode::Key6,
+    KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+];
+
+/// Selects a slot with the number keys or by scrolling
+pub fn select_hotbar_slot(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    mut hotbar: ResMut<Hotbar>,
+) {
+    for (index, key) in SLOT_KEYS.iter().enumerate() {
+        if keyboard_input.just_pressed(*key) {
+            hotbar.selected = index;
+        }
+    }
+    for event in mouse_wheel.iter() {
+        let step = if event.y > 0.0 { HOTBAR_SIZE - 1 } else if event.y < 0.0 { 1 } else { 0 };
+        hotbar.selected = (hotbar.selected + step) % HOTBAR_SIZE;
+    }
+}
+
+/// Copies the block the player looks at into the hotbar with the middle button
+pub fn pick_block(
+    mouse: Res<Input<MouseButton>>,
+    registry: Res<BlockRegistry>,
+    seen_block: Query<&Block, With<SeenObject>>,
+    mut hotbar: ResMut<Hotbar>,
+) {
+    if !mouse.just_pressed(MouseButton::Middle) {
+        return;
+    }
+    if let Ok(block) = seen_block.get_single() {
+        hotbar.pick(registry.state(block.id).block);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,98 @@
+use bevy::prelude::*;
+
+/// How the player plays, it decides the rules of reaching, breaking and placing blocks
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum GameMode {
+    #[default]
+    Survival,
+    Creative,
+}
+
+/// The rules of reaching, breaking and placing blocks in one game mode
+#[derive(Clone, Copy, Debug)]
+pub struct InteractionConfig {
+    /// Blocks farther than this from the eye can not be aimed at
+    pub reach: f32,
+    /// Seconds it takes to break a block, None breaks it at once
+    pub break_time: Option<f32>,
+    /// Seconds between two actions while a button is held, None only acts once per click
+    pub repeat_delay: Option<f32>,
+}
+
+/// The current game mode and the interaction rules of every mode
+pub struct InteractionRules {
+    pub mode: GameMode,
+    pub survival: InteractionConfig,
+    pub creative: InteractionConfig,
+}
+
+impl Default for InteractionRules {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::default(),
+            survival: InteractionConfig {
+                reach: 5.0,
+                break_time: Some(3.375),
+                repeat_delay: None,
+            },
+            creative: InteractionConfig {
+                reach: 8.0,
+                break_time: None,
+                repeat_delay: Some(0.2),
+            },
+        }
+    }
+}
+
+impl InteractionRules {
+    /// The rules of the current game mode
+    pub fn config(&self) -> &InteractionConfig {
+        match self.mode {
+            GameMode::Survival => &self.survival,
+            GameMode::Creative => &self.creative,
+        }
+    }
+}
+
+/// Tracks a mouse button so that an action fires on the click and then again every repeat delay while it is held
+#[derive(Default, Debug)]
+pub struct RepeatAction {
+    held: bool,
+    elapsed_time: f32,
+}
+
+impl RepeatAction {
+    /// Whether the action fires this frame
+    pub fn trigger(&mut self, pressed: bool, repeat_delay: Option<f32>, delta: f32) -> bool {
+        if !pressed {
+            self.held = false;
+            return false;
+        }
+        let ready = if !self.held {
+            true
+        } else if let Some(delay) = repeat_delay {
+            self.elapsed_time += delta;
+            self.elapsed_time >= delay
+        } else {
+            false
+        };
+        if ready {
+            self.held = true;
+            self.elapsed_time = 0.0;
+        }
+        ready
+    }
+}
+
+/// Switches between survival and creative with F4
+pub fn toggle_game_mode(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut rules: ResMut<InteractionRules>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F4) {
+        rules.mode = match rules.mode {
+            GameMode::Survival => GameMode::Creative,
+            GameMode::Creative => GameMode::Survival,
+        };
+    }
+}
\ No newline at end of file
@@ -0,0 +1,352 @@
+use bevy::prelude::*;
+
+use crate::{
+    chunk_mesh::ChunkMeshes,
+    registry::{
+        BlockRegistry,
+        StateId,
+        AIR
+    },
+    world::{
+        BlockChanged,
+        Chunk,
+        VoxelWorld,
+        CHUNK_HEIGHT,
+        CHUNK_SIZE,
+        NEIGHBOURS
+    }
+};
+
+use std::collections::{HashSet, VecDeque};
+
+pub const MAX_LIGHT: u8 = 15;
+
+/// The two kinds of light stored for every block
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LightChannel {
+    /// Light coming from the sky, it goes straight down through air without fading
+    Sky,
+    /// Light emitted by blocks
+    Block,
+}
+
+impl LightChannel {
+    pub fn unpack(self, packed: u8) -> u8 {
+        match self {
+            LightChannel::Sky => packed >> 4,
+            LightChannel::Block => packed & 0x0F,
+        }
+    }
+
+    pub fn pack(self, packed: u8, level: u8) -> u8 {
+        match self {
+            LightChannel::Sky => (packed & 0x0F) | (level << 4),
+            LightChannel::Block => (packed & 0xF0) | (level & 0x0F),
+        }
+    }
+}
+
+const DOWN: IVec3 = IVec3::new(0, -1, 0);
+
+// the level light has after moving from a block with level into the neighbour at offset
+fn spread_level(channel: LightChannel, level: u8, offset: IVec3, neighbour: StateId) -> u8 {
+    if channel == LightChannel::Sky && level == MAX_LIGHT && offset == DOWN && neighbour == AIR {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(1)
+    }
+}
+
+/// Spreads the light of the queued blocks with a breadth first flood fill
+///
+/// Every block whose light changed is added to changed
+fn propagate(
+    world: &mut VoxelWorld,
+    registry: &BlockRegistry,
+    channel: LightChannel,
+    mut queue: VecDeque<IVec3>,
+    changed: &mut HashSet<IVec3>,
+) {
+    while let Some(pos) = queue.pop_front() {
+        let level = world.light(pos, channel).unwrap_or(0);
+        if level <= 1 {
+            continue;
+        }
+        for offset in NEIGHBOURS {
+            let neighbour = pos + offset;
+            let id = match world.get_block_opt(neighbour) {
+                Some(id) if registry.is_transparent(id) => id,
+                _ => continue,
+            };
+            let spread = spread_level(channel, level, offset, id);
+            if world.light(neighbour, channel).unwrap_or(MAX_LIGHT) < spread {
+                world.set_light(neighbour, channel, spread);
+                changed.insert(neighbour);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+}
+
+/// Removes the light which came from pos, where the level used to be level
+///
+/// Returns the lit blocks at the border of the darkened area, they have to be propagated again
+fn unpropagate(
+    world: &mut VoxelWorld,
+    channel: LightChannel,
+    pos: IVec3,
+    level: u8,
+    changed: &mut HashSet<IVec3>,
+) -> VecDeque<IVec3> {
+    let mut removal = VecDeque::from([(pos, level)]);
+    let mut relight = VecDeque::new();
+    while let Some((pos, level)) = removal.pop_front() {
+        for offset in NEIGHBOURS {
+            let neighbour = pos + offset;
+            let neighbour_level = match world.light(neighbour, channel) {
+                Some(neighbour_level) if neighbour_level > 0 => neighbour_level,
+                _ => continue,
+            };
+            let from_above = channel == LightChannel::Sky
+                && offset == DOWN
+                && level == MAX_LIGHT
+                && neighbour_level == MAX_LIGHT;
+            if neighbour_level < level || from_above {
+                world.set_light(neighbour, channel, 0);
+                changed.insert(neighbour);
+                removal.push_back((neighbour, neighbour_level));
+            } else {
+                relight.push_back(neighbour);
+            }
+        }
+    }
+    relight
+}
+
+/// Computes the light a freshly generated chunk has on its own, before it joins the world
+///
+/// This only needs the chunk, so it can run in a background job
+pub fn light_new_chunk(chunk: Chunk, registry: &BlockRegistry, coord: IVec2) -> Chunk {
+    let mut world = VoxelWorld::default();
+    world.insert_chunk(coord, chunk);
+    let origin = IVec3::new(coord.x * CHUNK_SIZE, 0, coord.y * CHUNK_SIZE);
+    let mut sky = VecDeque::new();
+    let mut block = VecDeque::new();
+    for x in 0..CHUNK_SIZE {
+        for z in 0..CHUNK_SIZE {
+            // the sky reaches down every column until the first block which is not air
+            let mut open_sky = true;
+            for y in (0..CHUNK_HEIGHT).rev() {
+                let pos = origin + IVec3::new(x, y, z);
+                let id = world.get_block(pos);
+                open_sky &= id == AIR;
+                if open_sky {
+                    world.set_light(pos, LightChannel::Sky, MAX_LIGHT);
+                    sky.push_back(pos);
+                }
+                let emission = registry.def(id).light_emission;
+                if emission > 0 {
+                    world.set_light(pos, LightChannel::Block, emission);
+                    block.push_back(pos);
+                }
+            }
+        }
+    }
+    let mut changed = HashSet::new();
+    propagate(&mut world, registry, LightChannel::Sky, sky, &mut changed);
+    propagate(&mut world, registry, LightChannel::Block, block, &mut changed);
+    world.remove_chunk(coord).expect("the chunk was inserted above")
+}
+
+/// The blocks light may cross the borders of a lit chunk from, before it joins the world
+///
+/// Those are the lit blocks along its borders, whose light may spread out, and the blocks of the neighbours
+/// next to its transparent border blocks, whose light may spread in. Only the chunk has to be in world,
+/// so this can run in a background job
+pub fn border_light_seeds(world: &VoxelWorld, registry: &BlockRegistry, coord: IVec2) -> Vec<IVec3> {
+    let origin = IVec3::new(coord.x * CHUNK_SIZE, 0, coord.y * CHUNK_SIZE);
+    let mut seeds = Vec::new();
+    for y in 0..CHUNK_HEIGHT {
+        for i in 0..CHUNK_SIZE {
+            for (border, outward) in [
+                (IVec3::new(0, y, i), -IVec3::X),
+                (IVec3::new(CHUNK_SIZE - 1, y, i), IVec3::X),
+                (IVec3::new(i, y, 0), -IVec3::Z),
+                (IVec3::new(i, y, CHUNK_SIZE - 1), IVec3::Z),
+            ] {
+                let pos = origin + border;
+                let lit = [LightChannel::Sky, LightChannel::Block]
+                    .iter()
+                    .any(|channel| world.light(pos, *channel).unwrap_or(0) > 1);
+                if lit {
+                    seeds.push(pos);
+                }
+                if registry.is_transparent(world.get_block(pos)) {
+                    seeds.push(pos + outward);
+                }
+            }
+        }
+    }
+    seeds
+}
+
+/// Spreads the light across the borders of a chunk which was just inserted into the world,
+/// from the loaded chunks around it into it and the other way round, starting from its border_light_seeds
+pub fn light_chunk_borders(world: &mut VoxelWorld, registry: &BlockRegistry, seeds: Vec<IVec3>) {
+    let seeds = VecDeque::from(seeds);
+    let mut changed = HashSet::new();
+    propagate(world, registry, LightChannel::Sky, seeds.clone(), &mut changed);
+    propagate(world, registry, LightChannel::Block, seeds, &mut changed);
+}
+
+/// Updates the light around a block which was just replaced by new
+fn relight_block(
+    world: &mut VoxelWorld,
+    registry: &BlockRegistry,
+    pos: IVec3,
+    new: StateId,
+    changed: &mut HashSet<IVec3>,
+) {
+    for channel in [LightChannel::Sky, LightChannel::Block] {
+        let level = world.light(pos, channel).unwrap_or(0);
+        world.set_light(pos, channel, 0);
+        let mut relight = unpropagate(world, channel, pos, level, changed);
+        let emission = registry.def(new).light_emission;
+        if channel == LightChannel::Block && emission > 0 {
+            world.set_light(pos, channel, emission);
+            relight.push_back(pos);
+        }
+        propagate(world, registry, channel, relight, changed);
+    }
+    changed.insert(pos);
+}
+
+/// Updates the light around every changed block and marks the sections whose light changed
+pub fn update_light(
+    mut events: EventReader<BlockChanged>,
+    mut world: ResMut<VoxelWorld>,
+    registry: Res<BlockRegistry>,
+    mut chunk_meshes: ResMut<ChunkMeshes>,
+) {
+    let mut changed = HashSet::new();
+    for change in events.iter() {
+        relight_block(&mut world, &registry, change.pos, change.new, &mut changed);
+    }
+    for pos in changed {
+        chunk_meshes.mark_block(pos);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array3;
+
+    const LAMP_Y: i32 = 50;
+
+    fn state(registry: &BlockRegistry, name: &str) -> StateId {
+        registry.default_state_of(name).unwrap()
+    }
+
+    // a lit chunk made of the blocks place gives for every local position
+    fn lit_chunk(registry: &BlockRegistry, coord: IVec2, place: impl Fn(IVec3) -> StateId) -> Chunk {
+        let size = CHUNK_SIZE as usize;
+        let blocks = Array3::from_shape_fn((size, CHUNK_HEIGHT as usize, size), |(x, y, z)| {
+            place(IVec3::new(x as i32, y as i32, z as i32))
+        });
+        light_new_chunk(Chunk::from(blocks), registry, coord)
+    }
+
+    fn lamp_world(registry: &BlockRegistry) -> VoxelWorld {
+        let lamp = state(registry, "lamp");
+        let mut world = VoxelWorld::default();
+        let chunk = lit_chunk(registry, IVec2::ZERO, |pos| {
+            if pos == IVec3::new(8, LAMP_Y, 8) { lamp } else { AIR }
+        });
+        world.insert_chunk(IVec2::ZERO, chunk);
+        world
+    }
+
+    #[test]
+    fn sky_light_falls_down_a_shaft() {
+        let registry = BlockRegistry::default();
+        let rock = state(&registry, "rock");
+        // rock up to y 100 with a shaft down to y 10, and a side pocket at its bottom
+        let chunk = lit_chunk(&registry, IVec2::ZERO, |pos| {
+            let shaft = pos.x == 8 && pos.z == 8 && pos.y >= 10;
+            let pocket = pos == IVec3::new(9, 10, 8);
+            if pos.y < 100 && !shaft && !pocket { rock } else { AIR }
+        });
+        let mut world = VoxelWorld::default();
+        world.insert_chunk(IVec2::ZERO, chunk);
+        for y in 10..CHUNK_HEIGHT {
+            assert_eq!(world.light(IVec3::new(8, y, 8), LightChannel::Sky), Some(MAX_LIGHT), "y {}", y);
+        }
+        assert_eq!(world.light(IVec3::new(9, 10, 8), LightChannel::Sky), Some(MAX_LIGHT - 1));
+        assert_eq!(world.light(IVec3::new(8, 9, 8), LightChannel::Sky), Some(0));
+        assert_eq!(world.light(IVec3::new(7, 50, 8), LightChannel::Sky), Some(0));
+    }
+
+    #[test]
+    fn lamp_light_fades_one_level_per_block() {
+        let registry = BlockRegistry::default();
+        let world = lamp_world(&registry);
+        for distance in 0..8 {
+            let pos = IVec3::new(8 + distance, LAMP_Y, 8);
+            assert_eq!(world.light(pos, LightChannel::Block), Some(MAX_LIGHT - distance as u8), "{:?}", pos);
+        }
+        assert_eq!(world.light(IVec3::new(9, LAMP_Y + 1, 9), LightChannel::Block), Some(MAX_LIGHT - 3));
+        assert_eq!(world.light(IVec3::new(8, LAMP_Y + 15, 8), LightChannel::Block), Some(0));
+    }
+
+    #[test]
+    fn removing_the_lamp_darkens_everything() {
+        let registry = BlockRegistry::default();
+        let mut world = lamp_world(&registry);
+        let lamp_pos = IVec3::new(8, LAMP_Y, 8);
+        world.set_block(lamp_pos, AIR);
+        let mut changed = HashSet::new();
+        relight_block(&mut world, &registry, lamp_pos, AIR, &mut changed);
+        let chunk = world.chunk(IVec2::ZERO).unwrap();
+        assert!(chunk.light.iter().all(|packed| LightChannel::Block.unpack(*packed) == 0));
+        assert!(changed.contains(&(lamp_pos + IVec3::X)));
+        // the sky now reaches down through where the lamp was
+        assert_eq!(world.light(lamp_pos, LightChannel::Sky), Some(MAX_LIGHT));
+    }
+
+    #[test]
+    fn rock_borders_give_no_light_seeds() {
+        let registry = BlockRegistry::default();
+        let rock = state(&registry, "rock");
+        let mut world = VoxelWorld::default();
+        world.insert_chunk(IVec2::ZERO, lit_chunk(&registry, IVec2::ZERO, |pos| {
+            if pos.y < 100 { rock } else { AIR }
+        }));
+        let seeds = border_light_seeds(&world, &registry, IVec2::ZERO);
+        assert!(!seeds.is_empty());
+        assert!(seeds.iter().all(|seed| seed.y >= 100));
+    }
+
+    #[test]
+    fn light_crosses_chunk_borders() {
+        let registry = BlockRegistry::default();
+        let lamp = state(&registry, "lamp");
+        let east = IVec2::new(1, 0);
+        let mut world = VoxelWorld::default();
+        world.insert_chunk(IVec2::ZERO, lit_chunk(&registry, IVec2::ZERO, |pos| {
+            if pos == IVec3::new(14, LAMP_Y, 8) { lamp } else { AIR }
+        }));
+        // the seeds are found before the chunk joins the world, as the generation job does
+        let mut generated = VoxelWorld::default();
+        generated.insert_chunk(east, lit_chunk(&registry, east, |_| AIR));
+        let seeds = border_light_seeds(&generated, &registry, east);
+        world.insert_chunk(east, generated.remove_chunk(east).unwrap());
+        assert_eq!(world.light(IVec3::new(16, LAMP_Y, 8), LightChannel::Block), Some(0));
+        light_chunk_borders(&mut world, &registry, seeds);
+        for x in 15..26 {
+            let expected = MAX_LIGHT - (x - 14) as u8;
+            assert_eq!(world.light(IVec3::new(x, LAMP_Y, 8), LightChannel::Block), Some(expected), "x {}", x);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,236 @@
+use bevy::{
+    prelude::*,
+    tasks::{
+        AsyncComputeTaskPool,
+        Task
+    }
+};
+use bevy_rapier3d::prelude::RigidBody;
+use futures_lite::future;
+
+use crate::{
+    block::{
+        chunk_collider_candidates,
+        despawn_chunk_blocks,
+        refresh_block,
+        BlockEntities
+    },
+    chunk_mesh::ChunkMeshes,
+    generator::generate_chunk,
+    light::{
+        border_light_seeds,
+        light_chunk_borders
+    },
+    player::Player,
+    registry::{
+        BlockRegistry,
+        SharedRegistry,
+        AIR
+    },
+    world::{
+        Chunk,
+        VoxelWorld
+    }
+};
+
+use std::collections::{HashMap, VecDeque};
+
+/// At most this many chunks are generated at the same time
+const MAX_GENERATION_JOBS: usize = 4;
+/// At most this many generated chunks join the world per frame
+const MAX_CHUNKS_APPLIED: usize = 1;
+/// At most this many solid blocks get their collider refreshed per frame, the colliders of a new chunk
+/// are spawned over the next frames
+const MAX_COLLIDER_REFRESHES: usize = 2048;
+
+/// A chunk generated in a background job, with what can be worked out without the chunks around it
+pub struct GeneratedChunk {
+    chunk: Chunk,
+    /// The blocks which may need a collider, see chunk_collider_candidates
+    colliders: Vec<IVec3>,
+    /// The blocks light may cross the borders from, see border_light_seeds
+    light_seeds: Vec<IVec3>,
+}
+
+impl GeneratedChunk {
+    /// Generates and lights a chunk, this runs in a background job
+    fn new(registry: &BlockRegistry, seed: u64, coord: IVec2) -> Self {
+        let mut world = VoxelWorld::default();
+        world.insert_chunk(coord, generate_chunk(registry, seed, coord));
+        let colliders = chunk_collider_candidates(&world, registry, coord);
+        let light_seeds = border_light_seeds(&world, registry, coord);
+        let chunk = world.remove_chunk(coord).expect("the chunk was inserted above");
+        Self { chunk, colliders, light_seeds }
+    }
+}
+
+/// Generates the chunks around the player in background jobs and unloads the ones left behind
+pub struct ChunkLoader {
+    /// Chunks are kept loaded up to this many chunks away from the player
+    pub view_distance: i32,
+    seed: u64,
+    jobs: HashMap<IVec2, Task<GeneratedChunk>>,
+    /// The blocks whose collider still has to be refreshed, with the chunk whose loading queued them
+    colliders: VecDeque<(IVec2, Vec<IVec3>)>,
+}
+
+impl ChunkLoader {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            view_distance: 4,
+            seed,
+            jobs: HashMap::new(),
+            colliders: VecDeque::new(),
+        }
+    }
+
+    /// Whether the colliders of a loaded chunk are still being spawned
+    fn colliders_pending(&self, coord: IVec2) -> bool {
+        self.colliders.iter().any(|(queued, _)| *queued == coord)
+    }
+}
+
+// distance in chunks along the farthest axis
+fn chunk_distance(a: IVec2, b: IVec2) -> i32 {
+    (a - b).abs().max_element()
+}
+
+fn player_chunk(player: &Query<&Transform, With<Player>>) -> Option<IVec2> {
+    player.get_single()
+        .ok()
+        .map(|transform| VoxelWorld::chunk_coord(transform.translation.floor().as_ivec3()))
+}
+
+/// Starts jobs for the missing chunks around the player, nearest first, and cancels the jobs the player moved away from
+pub fn queue_chunk_generation(
+    mut loader: ResMut<ChunkLoader>,
+    world: Res<VoxelWorld>,
+    registry: Option<Res<SharedRegistry>>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let (registry, center) = match (registry, player_chunk(&player)) {
+        (Some(registry), Some(center)) => (registry, center),
+        _ => return,
+    };
+    let view_distance = loader.view_distance;
+    // dropping a task cancels it
+    loader.jobs.retain(|coord, _| chunk_distance(*coord, center) <= view_distance);
+
+    let mut missing = Vec::new();
+    for x in -view_distance..=view_distance {
+        for z in -view_distance..=view_distance {
+            let coord = center + IVec2::new(x, z);
+            if world.chunk(coord).is_none() && !loader.jobs.contains_key(&coord) {
+                missing.push(coord);
+            }
+        }
+    }
+    missing.sort_by_key(|coord| (*coord - center).length_squared());
+    for coord in missing {
+        if loader.jobs.len() >= MAX_GENERATION_JOBS {
+            break;
+        }
+        let registry = registry.0.clone();
+        let seed = loader.seed;
+        let job = AsyncComputeTaskPool::get().spawn(async move {
+            GeneratedChunk::new(&registry, seed, coord)
+        });
+        loader.jobs.insert(coord, job);
+    }
+}
+
+/// Inserts the generated chunks into the world and unloads the chunks which are too far from the player
+///
+/// The colliders of the new chunks are only queued, spawn_queued_colliders spawns them
+pub fn apply_generated_chunks(
+    mut commands: Commands,
+    mut loader: ResMut<ChunkLoader>,
+    mut world: ResMut<VoxelWorld>,
+    registry: Res<BlockRegistry>,
+    mut entities: ResMut<BlockEntities>,
+    mut chunk_meshes: ResMut<ChunkMeshes>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let mut finished = Vec::new();
+    for (coord, job) in loader.jobs.iter_mut() {
+        if finished.len() >= MAX_CHUNKS_APPLIED {
+            break;
+        }
+        if let Some(chunk) = future::block_on(future::poll_once(job)) {
+            finished.push((*coord, chunk));
+        }
+    }
+    for (coord, generated) in finished {
+        loader.jobs.remove(&coord);
+        world.insert_chunk(coord, generated.chunk);
+        light_chunk_borders(&mut world, &registry, generated.light_seeds);
+        loader.colliders.push_back((coord, generated.colliders));
+        // the faces and the light along the borders of the neighbours change as well
+        chunk_meshes.add_chunk(coord);
+        for offset in [IVec2::X, IVec2::Y, -IVec2::X, -IVec2::Y] {
+            chunk_meshes.mark_chunk(coord + offset);
+        }
+    }
+
+    let center = match player_chunk(&player) {
+        Some(center) => center,
+        None => return,
+    };
+    // a margin around the view distance keeps chunks from reloading when the player walks along a border
+    let far: Vec<IVec2> = world.chunk_coords()
+        .filter(|coord| chunk_distance(*coord, center) > loader.view_distance + 1)
+        .collect();
+    for coord in far {
+        world.remove_chunk(coord);
+        loader.colliders.retain(|(queued, _)| *queued != coord);
+        despawn_chunk_blocks(&mut commands, &mut entities, coord);
+        chunk_meshes.remove_chunk(&mut commands, coord);
+    }
+}
+
+/// Refreshes the colliders queued by the chunks which were loaded, oldest chunk first,
+/// until MAX_COLLIDER_REFRESHES solid blocks were refreshed this frame
+pub fn spawn_queued_colliders(
+    mut commands: Commands,
+    mut loader: ResMut<ChunkLoader>,
+    world: Res<VoxelWorld>,
+    registry: Res<BlockRegistry>,
+    mut entities: ResMut<BlockEntities>,
+) {
+    let mut budget = MAX_COLLIDER_REFRESHES;
+    while budget > 0 {
+        let positions = match loader.colliders.front_mut() {
+            Some((_, positions)) => positions,
+            None => return,
+        };
+        match positions.pop() {
+            Some(pos) => {
+                // air rarely has a collider to despawn, it is cheap and not counted
+                if world.get_block(pos) != AIR {
+                    budget -= 1;
+                }
+                refresh_block(&mut commands, &mut entities, &world, &registry, pos);
+            },
+            None => {
+                loader.colliders.pop_front();
+            }
+        }
+    }
+}
+
+/// Keeps the player from falling while the chunk it is in has not been generated yet,
+/// or its colliders have not all been spawned
+pub fn hold_player_in_unloaded_chunks(
+    world: Res<VoxelWorld>,
+    loader: Res<ChunkLoader>,
+    mut player: Query<(&Transform, &mut RigidBody), With<Player>>,
+) {
+    for (transform, mut body) in &mut player {
+        let coord = VoxelWorld::chunk_coord(transform.translation.floor().as_ivec3());
+        let loaded = world.chunk(coord).is_some() && !loader.colliders_pending(coord);
+        let wanted = if loaded { RigidBody::Dynamic } else { RigidBody::Fixed };
+        if *body != wanted {
+            *body = wanted;
+        }
+    }
+}
\ No newline at end of file
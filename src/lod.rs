@@ -0,0 +1,263 @@
+use bevy::{
+    prelude::*,
+    tasks::{
+        AsyncComputeTaskPool,
+        Task
+    }
+};
+use futures_lite::future;
+use noise::Perlin;
+
+use crate::{
+    atlas::BlockAtlas,
+    chunk_mesh::{
+        brightness,
+        sky_darkness,
+        ChunkMeshes,
+        MeshBuilder
+    },
+    generator::surface,
+    light::MAX_LIGHT,
+    player::Player,
+    registry::{
+        BlockRegistry,
+        RenderClass,
+        SharedRegistry
+    },
+    sky::material::Atmosphere,
+    world::{
+        Face,
+        VoxelWorld,
+        CHUNK_SIZE
+    }
+};
+
+use std::collections::HashMap;
+
+/// One step of the distance schedule of the distant terrain
+#[derive(Clone, Copy, Debug)]
+pub struct LodLevel {
+    /// Width in blocks of one cell of the heightmap, it has to divide CHUNK_SIZE
+    pub scale: i32,
+    /// The level is used for the chunks up to this many chunks away from the player
+    pub max_distance: i32,
+}
+
+/// How coarse the distant terrain gets with the distance, from the nearest level to the farthest
+pub struct LodSettings {
+    pub levels: Vec<LodLevel>,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            levels: vec![
+                LodLevel { scale: 2, max_distance: 8 },
+                LodLevel { scale: 4, max_distance: 12 },
+                LodLevel { scale: 8, max_distance: 16 },
+            ],
+        }
+    }
+}
+
+impl LodSettings {
+    /// Cell width of the tile of a chunk at the given distance, None beyond the last level
+    pub fn scale_at(&self, distance: i32) -> Option<i32> {
+        self.levels.iter().find(|level| distance <= level.max_distance).map(|level| level.scale)
+    }
+
+    pub fn max_distance(&self) -> i32 {
+        self.levels.last().map_or(0, |level| level.max_distance)
+    }
+}
+
+/// At most this many tiles are built at the same time
+const MAX_LOD_JOBS: usize = 4;
+/// At most this many finished tiles are uploaded per frame
+const MAX_LOD_UPLOADS: usize = 8;
+
+struct LodTile {
+    entity: Entity,
+    scale: i32,
+    sky_darkness: u8,
+}
+
+struct LodJob {
+    scale: i32,
+    sky_darkness: u8,
+    task: Task<MeshBuilder>,
+}
+
+/// The heightmap tiles standing in for the chunks which are not loaded or not meshed yet, one per chunk
+#[derive(Default)]
+pub struct LodTiles {
+    tiles: HashMap<IVec2, LodTile>,
+    jobs: HashMap<IVec2, LodJob>,
+}
+
+/// Builds the tile of a chunk from the surface of the generator, with cells of scale blocks
+///
+/// Walls drop down to the lower neighbours, and the edges of the tile hang skirts
+/// that hide the cracks against tiles of another scale and against full chunks
+pub fn build_lod_tile(registry: &BlockRegistry, coord: IVec2, scale: i32, sky_darkness: u8) -> MeshBuilder {
+    let perlin = Perlin::new();
+    let origin = coord * CHUNK_SIZE;
+    let cells = CHUNK_SIZE / scale;
+    // the surface in the middle of a cell, the cells around the tile are sampled as well
+    let sample = |i: i32, j: i32| {
+        surface(&perlin, registry, origin.x + i * scale + scale / 2, origin.y + j * scale + scale / 2)
+    };
+    let shade = brightness(MAX_LIGHT.saturating_sub(sky_darkness));
+    let size = scale as f32;
+    let mut builder = MeshBuilder::default();
+    for i in 0..cells {
+        for j in 0..cells {
+            let (height, top) = sample(i, j);
+            let min = Vec3::new((i * scale) as f32 - 0.5, 0.0, (j * scale) as f32 - 0.5);
+            let top_y = height as f32 + 0.5;
+            builder.add_stretched_face(
+                Vec3::new(min.x, top_y, min.z),
+                Vec3::new(min.x + size, top_y, min.z + size),
+                Face::Up,
+                registry.face_uv(top, Face::Up),
+                shade
+            );
+            for face in [Face::East, Face::South, Face::West, Face::North] {
+                let normal = face.normal();
+                let (next_i, next_j) = (i + normal.x, j + normal.z);
+                let (neighbour_height, _) = sample(next_i, next_j);
+                let edge = next_i < 0 || next_i >= cells || next_j < 0 || next_j >= cells;
+                let bottom = if edge {
+                    neighbour_height.min(height) - scale
+                } else if neighbour_height < height {
+                    neighbour_height
+                } else {
+                    continue;
+                };
+                builder.add_stretched_face(
+                    Vec3::new(min.x, bottom as f32 + 0.5, min.z),
+                    Vec3::new(min.x + size, top_y, min.z + size),
+                    face,
+                    registry.face_uv(top, face),
+                    shade
+                );
+            }
+        }
+    }
+    builder
+}
+
+/// Starts building the tiles which are missing or have the wrong scale, nearest first,
+/// and drops the tiles and jobs which are out of range
+pub fn queue_lod_tiles(
+    mut commands: Commands,
+    settings: Res<LodSettings>,
+    mut lod_tiles: ResMut<LodTiles>,
+    registry: Option<Res<SharedRegistry>>,
+    atlas: Option<Res<BlockAtlas>>,
+    atmosphere: Res<Atmosphere>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let (registry, transform) = match (registry, atlas, player.get_single()) {
+        (Some(registry), Some(_), Ok(transform)) => (registry, transform),
+        _ => return,
+    };
+    let center = VoxelWorld::chunk_coord(transform.translation.floor().as_ivec3());
+    let darkness = sky_darkness(atmosphere.sun_position);
+    let wanted_scale = |coord: IVec2| settings.scale_at((coord - center).abs().max_element());
+
+    // dropping a task cancels it
+    lod_tiles.jobs.retain(|coord, job| wanted_scale(*coord) == Some(job.scale));
+    let far: Vec<IVec2> = lod_tiles.tiles.keys()
+        .filter(|coord| wanted_scale(**coord).is_none())
+        .copied()
+        .collect();
+    for coord in far {
+        if let Some(tile) = lod_tiles.tiles.remove(&coord) {
+            commands.entity(tile.entity).despawn();
+        }
+    }
+
+    let range = settings.max_distance();
+    let mut missing = Vec::new();
+    for x in -range..=range {
+        for z in -range..=range {
+            let coord = center + IVec2::new(x, z);
+            let scale = match wanted_scale(coord) {
+                Some(scale) => scale,
+                None => continue,
+            };
+            let current = lod_tiles.tiles.get(&coord)
+                .map_or(false, |tile| tile.scale == scale && tile.sky_darkness == darkness);
+            let queued = lod_tiles.jobs.get(&coord)
+                .map_or(false, |job| job.sky_darkness == darkness);
+            if !current && !queued {
+                missing.push((coord, scale));
+            }
+        }
+    }
+    missing.sort_by_key(|(coord, _)| (*coord - center).length_squared());
+    for (coord, scale) in missing {
+        if lod_tiles.jobs.len() >= MAX_LOD_JOBS {
+            break;
+        }
+        let registry = registry.0.clone();
+        let task = AsyncComputeTaskPool::get().spawn(async move {
+            build_lod_tile(&registry, coord, scale, darkness)
+        });
+        lod_tiles.jobs.insert(coord, LodJob { scale, sky_darkness: darkness, task });
+    }
+}
+
+/// Replaces the tiles whose job is done
+pub fn apply_lod_tiles(
+    mut commands: Commands,
+    mut lod_tiles: ResMut<LodTiles>,
+    atlas: Option<Res<BlockAtlas>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let atlas = match atlas {
+        Some(atlas) => atlas,
+        None => return,
+    };
+    let mut finished = Vec::new();
+    for (coord, job) in lod_tiles.jobs.iter_mut() {
+        if finished.len() >= MAX_LOD_UPLOADS {
+            break;
+        }
+        if let Some(builder) = future::block_on(future::poll_once(&mut job.task)) {
+            finished.push((*coord, job.scale, job.sky_darkness, builder));
+        }
+    }
+    for (coord, scale, sky_darkness, builder) in finished {
+        lod_tiles.jobs.remove(&coord);
+        if let Some(tile) = lod_tiles.tiles.remove(&coord) {
+            commands.entity(tile.entity).despawn();
+        }
+        let origin = coord * CHUNK_SIZE;
+        let entity = commands.spawn_bundle(MaterialMeshBundle {
+            mesh: meshes.add(builder.build()),
+            material: atlas.material(RenderClass::Opaque),
+            transform: Transform::from_xyz(origin.x as f32, 0.0, origin.y as f32),
+            ..default()
+        }).id();
+        lod_tiles.tiles.insert(coord, LodTile { entity, scale, sky_darkness });
+    }
+}
+
+/// Hides the tiles of the chunks which are drawn at full detail
+pub fn hide_detailed_lod_tiles(
+    lod_tiles: Res<LodTiles>,
+    world: Res<VoxelWorld>,
+    chunk_meshes: Res<ChunkMeshes>,
+    mut visibilities: Query<&mut Visibility>,
+) {
+    for (coord, tile) in &lod_tiles.tiles {
+        let is_visible = world.chunk(*coord).is_none() || !chunk_meshes.is_chunk_meshed(*coord);
+        if let Ok(mut visibility) = visibilities.get_mut(tile.entity) {
+            if visibility.is_visible != is_visible {
+                visibility.is_visible = is_visible;
+            }
+        }
+    }
+}
\ No newline at end of file
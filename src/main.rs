@@ -1,62 +1,236 @@
 use bevy::{
     prelude::*,
     render::texture::ImageSettings,
+    time::FixedTimestep,
 };
 use bevy_rapier3d::prelude::{
     *,
 };
 
+mod atlas;
+mod behaviour;
 mod block;
+mod chunk_mesh;
 mod debugger;
+mod falling;
+mod fog;
 mod generator;
+mod hotbar;
+mod interaction;
+mod light;
+mod loader;
+mod lod;
+mod model;
+mod outline;
 mod player;
+mod registry;
+mod save;
+mod section;
 mod sky;
+mod spectator;
+mod state;
+mod terrain_material;
+mod tick;
 mod utils;
+mod visibility;
+mod weather;
+mod world;
+mod world_time;
 
+use atlas::{
+    build_block_atlas,
+    load_block_textures
+};
 use block::{
     control_block,
-    create_block
+    sync_block_entities,
+    BlockEntities
+};
+use chunk_mesh::{
+    apply_section_meshes,
+    queue_section_meshes,
+    sort_translucent_faces,
+    ChunkMeshes
 };
 use debugger::{
     Debugger, update_debugger
 };
-use generator::height_generator;
+use falling::{
+    land_falling_blocks,
+    setup_falling_blocks
+};
+use fog::{
+    update_fog,
+    Fog
+};
+use hotbar::{
+    pick_block,
+    select_hotbar_slot,
+    Hotbar
+};
+use interaction::{
+    toggle_game_mode,
+    InteractionRules
+};
+use light::update_light;
+use loader::{
+    apply_generated_chunks,
+    hold_player_in_unloaded_chunks,
+    queue_chunk_generation,
+    spawn_queued_colliders,
+    ChunkLoader
+};
+use lod::{
+    apply_lod_tiles,
+    hide_detailed_lod_tiles,
+    queue_lod_tiles,
+    LodSettings,
+    LodTiles
+};
+use outline::{
+    setup_block_outline,
+    update_block_outline
+};
 use player::{
     setup_player,
     ground_event,
     player_update,
     player_eye
 };
+use registry::{
+    share_registry,
+    BlockRegistry
+};
+use save::{
+    save_world,
+    SaveFile
+};
 use sky::{
     AtmospherePlugin,
-    AtmosphereTransform,
+    ATMOSPHERE_TRANSITION,
     material::Atmosphere,
+    preset::cycle_atmosphere_preset,
+};
+use spectator::{
+    follow_spectator,
+    toggle_spectator
+};
+use terrain_material::{
+    update_terrain_materials,
+    TerrainMaterialPlugin
+};
+use tick::{
+    schedule_neighbour_ticks,
+    world_tick,
+    TickScheduler,
+    TICK_STEP
+};
+use visibility::cull_sections;
+use weather::{
+    advance_weather,
+    setup_precipitation,
+    update_precipitation,
+    weather_sky
+};
+use world::{
+    emit_block_changes,
+    BlockChanged,
+    VoxelWorld
+};
+use world_time::{
+    advance_world_time,
+    apply_time_commands,
+    time_keys,
+    TimeCommand,
+    WorldTime
 };
 
 /// This example shows various ways to configure texture materials in 3D
 fn main() {
+    let save = SaveFile::load();
     App::new()
         .insert_resource(Msaa { samples: 4 })
         .insert_resource(Atmosphere::default())
-        .insert_resource(AtmosphereTransform::default())
         .insert_resource(ImageSettings::default_nearest())
         .insert_resource(Debugger::default())
+        .insert_resource(BlockRegistry::default())
+        .init_resource::<Hotbar>()
+        .insert_resource(InteractionRules::default())
+        .insert_resource(VoxelWorld::default())
+        .insert_resource(BlockEntities::default())
+        .insert_resource(ChunkMeshes::default())
+        .insert_resource(TickScheduler::new(WORLD_SEED))
+        .insert_resource(ChunkLoader::new(WORLD_SEED))
+        .insert_resource(save.time)
+        .insert_resource(save.weather)
+        .insert_resource(Fog::default())
+        .insert_resource(LodSettings::default())
+        .insert_resource(LodTiles::default())
+        .add_event::<BlockChanged>()
+        .add_event::<TimeCommand>()
         .add_plugins(DefaultPlugins)
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
         //.add_plugin(RapierDebugRenderPlugin::default()) //collision debugging
         .add_plugin(AtmospherePlugin::default())
+        .add_plugin(TerrainMaterialPlugin)
         .add_startup_system(setup_player)
         .add_startup_system(setup_environment)
-        .add_startup_system(terrain_generation)
+        .add_startup_system(load_block_textures)
+        .add_startup_system(setup_block_outline)
+        .add_startup_system(setup_precipitation)
         .add_system(ground_event)
         .add_system(player_update)
         .add_system(update_debugger)
-        .add_system(daylight_cycle)
+        .add_system(toggle_spectator.before("spectator"))
+        .add_system(follow_spectator.label("spectator"))
+        .add_system(time_keys.before("time_commands"))
+        .add_system(apply_time_commands.label("time_commands"))
+        .add_system(daylight_cycle.label("sky").after("time_commands"))
+        .add_system(cycle_atmosphere_preset.before(ATMOSPHERE_TRANSITION))
+        .add_system(weather_sky.label("sky").after(ATMOSPHERE_TRANSITION))
+        .add_system(update_fog.label("fog"))
+        .add_system(update_terrain_materials.after("sky").after("fog"))
+        .add_system(update_precipitation)
+        .add_system(toggle_game_mode.before("raycast").before("block_control"))
         .add_system(player_eye.label("raycast"))
-        .add_system(control_block.after("raycast"))
+        .add_system(update_block_outline.after("block_control"))
+        .add_system(select_hotbar_slot.before("block_control"))
+        .add_system(pick_block.after("raycast").before("block_control"))
+        .add_system(control_block.label("block_control").after("raycast"))
+        .add_system_set(
+            SystemSet::new()
+                .with_run_criteria(FixedTimestep::step(TICK_STEP))
+                .with_system(world_tick.label("world_tick"))
+                .with_system(advance_world_time.before("time_commands"))
+                .with_system(advance_weather)
+        )
+        .add_system(emit_block_changes.label("block_changes").after("block_control").after("world_tick"))
+        .add_system(sync_block_entities.after("block_changes"))
+        .add_system(update_light.label("light").after("block_changes"))
+        .add_system(build_block_atlas.label("atlas"))
+        .add_system(share_registry.label("share_registry").after("atlas"))
+        .add_system(queue_chunk_generation.after("share_registry"))
+        .add_system(apply_generated_chunks.label("chunk_loading").before("block_control"))
+        .add_system(spawn_queued_colliders.label("colliders").after("chunk_loading").before("block_control"))
+        .add_system(hold_player_in_unloaded_chunks.after("colliders"))
+        .add_system(queue_section_meshes.label("mesh_jobs").after("light").after("chunk_loading").after("share_registry"))
+        .add_system(apply_section_meshes.label("chunk_meshes").after("mesh_jobs"))
+        .add_system(sort_translucent_faces.after("chunk_meshes"))
+        .add_system(cull_sections.after("chunk_meshes"))
+        .add_system(queue_lod_tiles.label("lod_jobs").after("share_registry"))
+        .add_system(apply_lod_tiles.label("lod_tiles").after("lod_jobs"))
+        .add_system(hide_detailed_lod_tiles.after("lod_tiles").after("chunk_meshes"))
+        .add_system(schedule_neighbour_ticks.after("block_changes"))
+        .add_system(setup_falling_blocks)
+        .add_system(land_falling_blocks.before("block_changes"))
+        // the app stops right after the frame which sends AppExit, the last stage still sees it
+        .add_system_to_stage(CoreStage::Last, save_world)
         .run();
 }
 
+// seeds the simulation and the terrain so that the same world always evolves the same way
+const WORLD_SEED: u64 = 0;
+
 // the component for identify sun and moon
 #[derive(Component)]
 struct SunOrMoon {
@@ -67,29 +241,31 @@ struct SunOrMoon {
 fn daylight_cycle(
     mut sky_mat: ResMut<Atmosphere>,
     mut query: Query<(&mut Transform, &mut DirectionalLight, &SunOrMoon)>,
-    time: Res<Time>,
+    time: Res<WorldTime>,
+    clock: Res<Time>,
 ) {
     let mut pos = sky_mat.sun_position;
-    let t = time.time_since_startup().as_millis() as f32 / 200000.0;
+    let t = time.sun_angle();
     pos.y = t.sin();
     pos.z = t.cos();
     sky_mat.sun_position = pos;
+    // the moon stays opposite to the sun and waxes and wanes over the days
+    let moon = -pos;
+    sky_mat.moon_position = moon;
+    sky_mat.moon_phase = time.moon_cycle();
+    // the stars turn with the sun around the celestial pole
+    sky_mat.star_rotation = t;
+    sky_mat.time = clock.seconds_since_startup() as f32;
 
     for (mut light_trans, mut directional, sun_type) in &mut query {
         if sun_type.is_sun {
             light_trans.rotation = Quat::from_rotation_x(-pos.y.atan2(pos.z));
             directional.illuminance = t.sin().max(0.0).powf(2.0) * 100000.0;
         } else {
-            light_trans.rotation = Quat::from_rotation_x(pos.y.atan2(pos.z));
-            directional.illuminance = t.sin().max(0.0).powf(2.0) * 1000.0;
+            light_trans.rotation = Quat::from_rotation_x(-moon.y.atan2(moon.z));
+            directional.illuminance = moon.y.max(0.0).powf(2.0) * 1000.0 * time.moon_brightness();
         }
     }
-    /*
-    if let Some((mut light_trans, mut directional)) = query.single_mut().into() {
-        light_trans.rotation = Quat::from_rotation_x(-pos.y.atan2(pos.z));
-        directional.illuminance = t.sin().max(0.0).powf(2.0) * 100000.0;
-    }
-    */
 }
 
 fn setup_environment(
@@ -108,41 +284,4 @@ fn setup_environment(
             ..Default::default()
         })
         .insert(SunOrMoon{ is_sun: false }); // Marks the light as Moon
-}
-
-fn terrain_generation(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    /*
-    for i in -3..3 {
-        for j in -3..3 {
-            create_block(&mut commands, &asset_server, &mut meshes, &mut materials, Vec3::new(i as f32, 0.0, j as f32))
-        }
-    }
-    for i in -3..3 {
-        for j in 3..6 {
-            create_block(&mut commands, &asset_server, &mut meshes, &mut materials, Vec3::new(i as f32, -1.0, j as f32))
-        }
-    }
-    */
-    let map = height_generator(-15, -15);
-    for x in 0..16 {
-        for y in 60..66 {
-            for z in 0..16 {
-                match map[[x, y, z]] {
-                    0 => {},
-                    1 => {
-                        create_block(&mut commands, &asset_server, "textures/block/water.png", &mut meshes, &mut materials, Vec3::new(x as f32, y as f32, z as f32))
-                    },
-                    2 => {
-                        create_block(&mut commands, &asset_server, "textures/block/stone.png", &mut meshes, &mut materials, Vec3::new(x as f32, y as f32, z as f32))
-                    },
-                    _=>{}
-                }
-            }
-        }
-    }
 }
\ No newline at end of file
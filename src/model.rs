@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::Collider;
+use serde::Deserialize;
+
+use crate::{
+    registry::BlockRegistry,
+    state::{
+        Half,
+        StateProperties
+    },
+    world::{
+        BlockView,
+        Face
+    }
+};
+
+/// Axis aligned box in block space, where a full block spans -0.5..0.5 on every axis
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ModelBox {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl ModelBox {
+    pub const FULL: ModelBox = ModelBox::new(Vec3::splat(-0.5), Vec3::splat(0.5));
+
+    pub const fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) / 2.0
+    }
+
+    /// The box mirrored between the lower and the upper half of the block
+    pub fn flipped(&self) -> Self {
+        Self::new(
+            Vec3::new(self.min.x, -self.max.y, self.min.z),
+            Vec3::new(self.max.x, -self.min.y, self.max.z),
+        )
+    }
+
+    /// Whether the face of the box lies on the face of the block
+    pub fn touches(&self, face: Face) -> bool {
+        let normal = face.normal().as_vec3();
+        let extent = if normal.max_element() > 0.0 { self.max } else { self.min };
+        (extent * normal).element_sum() >= 0.5
+    }
+}
+
+/// The geometry of a block, named in assets/blocks.json
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockShape {
+    #[default]
+    Cube,
+    /// Half a block, in the half given by the half property
+    Slab,
+    /// The high step is on the side given by the facing property, upside down in the top half
+    Stairs,
+    /// A post which connects to the neighbouring fences and full blocks
+    Fence,
+    /// Two crossed planes, like plants
+    Cross,
+    /// A thin board lying in the half of the block given by half, or standing on the facing side when open
+    Trapdoor,
+}
+
+/// The horizontal faces a fence can connect through
+pub const FENCE_FACES: [Face; 4] = [Face::East, Face::South, Face::West, Face::North];
+
+impl BlockShape {
+    /// Boxes the block is drawn with, connections has a bit for each of FENCE_FACES the block connects to
+    ///
+    /// Cross has no boxes, its planes are built by the mesher
+    pub fn boxes(self, properties: StateProperties, connections: u8) -> Vec<ModelBox> {
+        let boxes = match self {
+            BlockShape::Cube => vec![ModelBox::FULL],
+            BlockShape::Slab => vec![ModelBox::new(Vec3::splat(-0.5), Vec3::new(0.5, 0.0, 0.5))],
+            BlockShape::Stairs => {
+                // the upper half only covers the half of the block on the facing side
+                let normal = properties.facing.normal().as_vec3();
+                let min = Vec3::new(-0.5, 0.0, -0.5) + normal.max(Vec3::ZERO) * 0.5;
+                let max = Vec3::splat(0.5) + normal.min(Vec3::ZERO) * 0.5;
+                vec![
+                    ModelBox::new(Vec3::splat(-0.5), Vec3::new(0.5, 0.0, 0.5)),
+                    ModelBox::new(min, max),
+                ]
+            },
+            BlockShape::Trapdoor if properties.open => {
+                let normal = properties.facing.normal().as_vec3();
+                let min = Vec3::splat(-0.5) + normal.max(Vec3::ZERO) * 0.8125;
+                let max = Vec3::splat(0.5) + normal.min(Vec3::ZERO) * 0.8125;
+                // an open trapdoor stands on its side whatever half it was in
+                return vec![ModelBox::new(min, max)];
+            },
+            BlockShape::Trapdoor => vec![ModelBox::new(Vec3::splat(-0.5), Vec3::new(0.5, -0.3125, 0.5))],
+            BlockShape::Fence => {
+                let mut boxes = vec![ModelBox::new(Vec3::new(-0.125, -0.5, -0.125), Vec3::new(0.125, 0.5, 0.125))];
+                for (index, face) in FENCE_FACES.iter().enumerate() {
+                    if connections & (1 << index) == 0 {
+                        continue;
+                    }
+                    let normal = face.normal().as_vec3();
+                    // a rail from the post to the side of the block, at two heights
+                    let near = normal * 0.125;
+                    let far = normal * 0.5;
+                    let width = Vec3::splat(0.0625) * (Vec3::ONE - normal.abs());
+                    for (bottom, top) in [(-0.125, 0.0625), (0.1875, 0.375)] {
+                        let min = near.min(far) - width;
+                        let max = near.max(far) + width;
+                        boxes.push(ModelBox::new(
+                            Vec3::new(min.x, bottom, min.z),
+                            Vec3::new(max.x, top, max.z),
+                        ));
+                    }
+                }
+                boxes
+            },
+            BlockShape::Cross => Vec::new(),
+        };
+        match properties.half {
+            Half::Bottom => boxes,
+            Half::Top => boxes.iter().map(ModelBox::flipped).collect(),
+        }
+    }
+
+    /// Boxes the block collides with, empty for blocks which can be walked through
+    pub fn collision_boxes(self, properties: StateProperties, connections: u8) -> Vec<ModelBox> {
+        match self {
+            BlockShape::Cross => Vec::new(),
+            _ => self.boxes(properties, connections),
+        }
+    }
+
+    /// Boxes the player aims at
+    pub fn selection_boxes(self, properties: StateProperties, connections: u8) -> Vec<ModelBox> {
+        match self {
+            BlockShape::Cross => vec![ModelBox::new(Vec3::new(-0.3, -0.5, -0.3), Vec3::new(0.3, 0.3, 0.3))],
+            _ => self.boxes(properties, connections),
+        }
+    }
+}
+
+/// The FENCE_FACES bits through which the block at pos connects to its neighbours
+pub fn connections(world: &impl BlockView, registry: &BlockRegistry, pos: IVec3) -> u8 {
+    if registry.def(world.get_block(pos)).shape != BlockShape::Fence {
+        return 0;
+    }
+    let mut connections = 0;
+    for (index, face) in FENCE_FACES.iter().enumerate() {
+        let connects = match world.get_block_opt(pos + face.normal()) {
+            Some(neighbour) => {
+                registry.def(neighbour).shape == BlockShape::Fence || !registry.is_transparent(neighbour)
+            },
+            None => false,
+        };
+        if connects {
+            connections |= 1 << index;
+        }
+    }
+    connections
+}
+
+/// A collider made of the given boxes, none when there are no boxes
+pub fn collider(boxes: &[ModelBox]) -> Option<Collider> {
+    match boxes {
+        [] => None,
+        [single] if *single == ModelBox::FULL => Some(Collider::cuboid(0.5, 0.5, 0.5)),
+        _ => Some(Collider::compound(
+            boxes.iter()
+                .map(|model_box| {
+                    let half = model_box.half_extents();
+                    (model_box.center(), Quat::IDENTITY, Collider::cuboid(half.x, half.y, half.z))
+                })
+                .collect()
+        )),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,117 @@
+use bevy::{
+    prelude::*,
+    render::mesh::PrimitiveTopology
+};
+
+use crate::{
+    block::Block,
+    model::{
+        connections,
+        ModelBox
+    },
+    player::SeenObject,
+    registry::{
+        BlockRegistry,
+        StateId
+    },
+    world::VoxelWorld
+};
+
+/// The outline is pushed out of the block a little, so that it is not hidden inside its faces
+const OUTLINE_MARGIN: f32 = 0.002;
+
+/// The wireframe drawn around the block the player looks at
+#[derive(Component, Default)]
+pub struct BlockOutline {
+    /// The state and fence connections the mesh was built for
+    shape: Option<(StateId, u8)>,
+}
+
+// the twelve edges of every box, as pairs of points relative to the block
+fn outline_mesh(boxes: &[ModelBox]) -> Mesh {
+    let mut positions = Vec::new();
+    for model_box in boxes {
+        let min = model_box.min - OUTLINE_MARGIN;
+        let max = model_box.max + OUTLINE_MARGIN;
+        let corner = |x: bool, y: bool, z: bool| [
+            if x { max.x } else { min.x },
+            if y { max.y } else { min.y },
+            if z { max.z } else { min.z },
+        ];
+        for a in [false, true] {
+            for b in [false, true] {
+                positions.extend([corner(false, a, b), corner(true, a, b)]);
+                positions.extend([corner(a, false, b), corner(a, true, b)]);
+                positions.extend([corner(a, b, false), corner(a, b, true)]);
+            }
+        }
+    }
+    let count = positions.len();
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 1.0, 0.0]; count]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; count]);
+    mesh
+}
+
+pub fn setup_block_outline(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn_bundle(PbrBundle {
+        mesh: meshes.add(outline_mesh(&[])),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgba(0.0, 0.0, 0.0, 0.6),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+        visibility: Visibility { is_visible: false },
+        ..default()
+    })
+    .insert(BlockOutline::default());
+}
+
+/// Moves the outline onto the block the player looks at, in the shape of its collider,
+/// and hides it when no block is within reach
+pub fn update_block_outline(
+    world: Res<VoxelWorld>,
+    registry: Res<BlockRegistry>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    seen_block: Query<&Block, With<SeenObject>>,
+    mut outline: Query<(&mut BlockOutline, &mut Transform, &mut Visibility, &Handle<Mesh>)>,
+) {
+    let (mut outline, mut transform, mut visibility, mesh) = match outline.get_single_mut() {
+        Ok(outline) => outline,
+        _ => return,
+    };
+    let block = match seen_block.get_single() {
+        Ok(block) => block,
+        _ => {
+            if visibility.is_visible {
+                visibility.is_visible = false;
+            }
+            return;
+        }
+    };
+    let pos = block.coord.round().as_ivec3();
+    let shape = (block.id, connections(&*world, &registry, pos));
+    if outline.shape != Some(shape) {
+        let def = registry.def(block.id);
+        let properties = registry.properties(block.id);
+        // blocks without a collision shape are aimed at through their selection boxes
+        let mut boxes = def.shape.collision_boxes(properties, shape.1);
+        if boxes.is_empty() {
+            boxes = def.shape.selection_boxes(properties, shape.1);
+        }
+        if let Some(mesh) = meshes.get_mut(mesh) {
+            *mesh = outline_mesh(&boxes);
+        }
+        outline.shape = Some(shape);
+    }
+    transform.translation = block.coord;
+    if !visibility.is_visible {
+        visibility.is_visible = true;
+    }
+}
\ No newline at end of file
@@ -11,7 +11,11 @@ use bevy_rapier3d::prelude::{
 };
 
 use crate::{
-    sky::AtmosphereTransform,
+    interaction::{
+        InteractionRules,
+        RepeatAction
+    },
+    sky::AtmosphereCamera,
     utils::to_radians
 };
 
@@ -25,7 +29,14 @@ pub struct PlayerStatus {
     on_ground: bool,
     jump_velocity: Vec3,
     pub see_at: Vec3,
-    pub block_put: bool
+    /// Normal of the surface the player looks at
+    pub see_normal: Vec3,
+    /// Direction the player looks in
+    pub see_direction: Vec3,
+    /// The right button, which places blocks
+    pub placing: RepeatAction,
+    /// The left button while blocks break at once
+    pub breaking: RepeatAction
 }
 
 impl PlayerStatus {   
@@ -39,6 +50,10 @@ impl PlayerStatus {
 #[derive(Component)]
 pub struct Player;
 
+/// The camera the player looks through, the terrain is culled, sorted and fogged for it
+#[derive(Component)]
+pub struct PlayerCamera;
+
 pub fn setup_player(
     mut commands: Commands,
     mut windows: ResMut<Windows>,
@@ -73,7 +88,9 @@ pub fn setup_player(
                 // when you want to see your self, change the coordinate of z
                 transform: Transform::from_xyz(0.0, 0.5, 0.0),
                 ..default()
-            });
+            })
+            .insert(AtmosphereCamera)
+            .insert(PlayerCamera);
         });
     
         //create cursor
@@ -127,10 +144,9 @@ pub fn ground_event(
 pub fn player_update(
     keyboard_input: Res<Input<KeyCode>>,
     mouse_motion: Res<Events<MouseMotion>>,
-    mut sky_trans: ResMut<AtmosphereTransform>,
     time: Res<Time>,
-    mut player_entity: Query<(&mut Transform, &mut PlayerStatus), (With<Player>, Without<Camera3d>)>,
-    mut camera_transforms: Query<&mut Transform, (With<Camera3d>, Without<Player>)>,
+    mut player_entity: Query<(&mut Transform, &mut PlayerStatus), (With<Player>, Without<PlayerCamera>)>,
+    mut camera_transforms: Query<&mut Transform, (With<PlayerCamera>, Without<Player>)>,
 ) {
     const TURNOVER_RATE: f32 = 0.25;
     let (mut transform, mut status) = match player_entity.iter_mut().next() {
@@ -183,7 +199,6 @@ pub fn player_update(
     if velocity != Vec3::ZERO {
         let dv = velocity * time.delta_seconds()*3.0;
         transform.translation += dv;
-        sky_trans.update(transform.translation);
     }
 
     //event reader
@@ -208,8 +223,9 @@ pub fn player_eye(
     mut commands: Commands,
     rapier_context: Res<RapierContext>,
     mut player: Query<(Entity, &mut PlayerStatus), With<Player>>,
-    camera: Query<&GlobalTransform, With<Camera3d>>,
-    seen_object: Query<Entity, With<SeenObject>>
+    camera: Query<&GlobalTransform, With<PlayerCamera>>,
+    seen_object: Query<Entity, With<SeenObject>>,
+    rules: Res<InteractionRules>
 ) {
     let (player_handle, mut status)
         = match player.get_single_mut() {
@@ -237,14 +253,16 @@ pub fn player_eye(
     
     let ray_ori = transform.translation();
     let ray_dir = transform.forward();
-    let max_toi = 5.0;
+    let max_toi = rules.config().reach;
     let solid = true;
     let filter = QueryFilter::new().exclude_rigid_body(player_handle);
+    status.see_direction = ray_dir;
 
-    if let Some((entity, toi)) = rapier_context.cast_ray(
+    if let Some((entity, intersection)) = rapier_context.cast_ray_and_get_normal(
         ray_ori, ray_dir, max_toi, solid, filter
     ) {
-        status.see_at = ray_ori + ray_dir * toi;
+        status.see_at = intersection.point;
+        status.see_normal = intersection.normal;
         commands.entity(entity).insert(SeenObject);
     }
 }
\ No newline at end of file
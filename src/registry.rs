@@ -0,0 +1,290 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    model::BlockShape,
+    state::{
+        Property,
+        StateProperties
+    },
+    tick::TickContext,
+    world::Face
+};
+
+use std::{
+    collections::HashMap,
+    sync::Arc
+};
+
+/// The type of a block, as numbered in assets/blocks.json
+pub type BlockId = u32;
+
+/// An index into the global state palette, which is what the world stores for every position
+pub type StateId = u32;
+
+/// The state of the empty block, every unloaded or cleared position reads as this
+pub const AIR: StateId = 0;
+
+/// Definition of a block as it is written in assets/blocks.json
+#[derive(Deserialize, Clone, Debug)]
+pub struct BlockDef {
+    pub name: String,
+    pub id: BlockId,
+    /// Texture used for the faces which have no texture of their own, none for blocks which are never drawn
+    #[serde(default)]
+    pub texture: Option<String>,
+    /// Textures of single faces or groups of faces
+    #[serde(default)]
+    pub textures: FaceTextures,
+    /// The geometry of the block, blocks which do not fill their cell should also be transparent
+    #[serde(default)]
+    pub shape: BlockShape,
+    /// The properties the states of the block differ by
+    #[serde(default)]
+    pub properties: Vec<Property>,
+    /// Whether the blocks behind this one can be seen through it
+    #[serde(default)]
+    pub transparent: bool,
+    /// The pass the block is drawn in, see-through textures also need transparent
+    #[serde(default)]
+    pub render_class: RenderClass,
+    /// Whether the block fills its whole cell, falling blocks only settle on full blocks
+    #[serde(default = "default_true")]
+    pub full: bool,
+    /// Whether the block falls when the block under it is removed
+    #[serde(default)]
+    pub falls_with_gravity: bool,
+    /// Level of the block light the block gives off, from 0 to 15
+    #[serde(default)]
+    pub light_emission: u8,
+    /// Whether placing a block into this one replaces it
+    #[serde(default)]
+    pub replaceable: bool,
+}
+
+/// Per-face textures of a block, the most specific one wins:
+/// a face's own texture, then top or bottom for Up and Down and side for the others, then the block's texture
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct FaceTextures {
+    pub top: Option<String>,
+    pub bottom: Option<String>,
+    pub side: Option<String>,
+    pub east: Option<String>,
+    pub south: Option<String>,
+    pub west: Option<String>,
+    pub north: Option<String>,
+}
+
+impl BlockDef {
+    /// Path of the texture drawn on a face
+    pub fn face_texture(&self, face: Face) -> Option<&str> {
+        let textures = &self.textures;
+        let specific = match face {
+            Face::East => textures.east.as_ref().or(textures.side.as_ref()),
+            Face::South => textures.south.as_ref().or(textures.side.as_ref()),
+            Face::West => textures.west.as_ref().or(textures.side.as_ref()),
+            Face::North => textures.north.as_ref().or(textures.side.as_ref()),
+            Face::Up => textures.top.as_ref(),
+            Face::Down => textures.bottom.as_ref(),
+        };
+        specific.or(self.texture.as_ref()).map(String::as_str)
+    }
+
+    /// Whether any face of the block is drawn
+    pub fn is_drawn(&self) -> bool {
+        Face::ALL.iter().any(|face| self.face_texture(*face).is_some())
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// The pass a block is drawn in, each pass uses a single material
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderClass {
+    #[default]
+    Opaque = 0,
+    /// Fully opaque or fully transparent texels, tested against an alpha threshold
+    Cutout = 1,
+    /// Blended with what is behind it
+    Translucent = 2,
+}
+
+/// Called with the position of the block which is being updated
+pub type TickHandler = fn(&mut TickContext, IVec3);
+
+/// Simulation hooks of a block type, every hook is optional
+#[derive(Clone, Copy, Default)]
+pub struct BlockBehaviour {
+    /// Runs when a tick which was scheduled for the block is due
+    pub on_scheduled_tick: Option<TickHandler>,
+    /// Runs when the block is picked by the random tick
+    pub on_random_tick: Option<TickHandler>,
+    /// Delay in ticks of the update scheduled when a neighbour changes
+    pub neighbour_tick_delay: Option<u32>,
+}
+
+/// One entry of the global state palette, a block with a value for each of its properties
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BlockState {
+    pub block: BlockId,
+    pub properties: StateProperties,
+}
+
+/// All the known block types, indexed by their id, and all their states
+///
+/// The states of a block are numbered consecutively, its default state first
+#[derive(Clone)]
+pub struct BlockRegistry {
+    defs: Vec<BlockDef>,
+    names: HashMap<String, BlockId>,
+    behaviours: Vec<BlockBehaviour>,
+    states: Vec<BlockState>,
+    state_ids: HashMap<BlockState, StateId>,
+    /// The default state of each block
+    default_states: Vec<StateId>,
+    /// Minimum and maximum uv of each face of each state in the block atlas, in the order of Face
+    face_uvs: Vec<[[Vec2; 2]; 6]>,
+}
+
+impl BlockRegistry {
+    pub fn from_json(json: &str) -> Self {
+        let mut defs: Vec<BlockDef> = serde_json::from_str(json).expect("invalid block definitions");
+        defs.sort_by_key(|def| def.id);
+        for (index, def) in defs.iter().enumerate() {
+            assert_eq!(def.id as usize, index, "block ids must be contiguous from 0");
+        }
+        let names = defs.iter().map(|def| (def.name.clone(), def.id)).collect();
+        let behaviours = vec![BlockBehaviour::default(); defs.len()];
+        let mut states = Vec::new();
+        let mut default_states = Vec::new();
+        for def in &defs {
+            default_states.push(states.len() as StateId);
+            states.extend(
+                StateProperties::combinations(&def.properties)
+                    .into_iter()
+                    .map(|properties| BlockState { block: def.id, properties })
+            );
+        }
+        let state_ids = states.iter().enumerate().map(|(index, state)| (*state, index as StateId)).collect();
+        let face_uvs = vec![[[Vec2::ZERO, Vec2::ONE]; 6]; states.len()];
+        Self {
+            defs,
+            names,
+            behaviours,
+            states,
+            state_ids,
+            default_states,
+            face_uvs,
+        }
+    }
+
+    pub fn id(&self, name: &str) -> Option<BlockId> {
+        self.names.get(name).copied()
+    }
+
+    /// Definition of the block a state belongs to
+    pub fn def(&self, state: StateId) -> &BlockDef {
+        &self.defs[self.state(state).block as usize]
+    }
+
+    pub fn block_def(&self, id: BlockId) -> &BlockDef {
+        &self.defs[id as usize]
+    }
+
+    pub fn defs(&self) -> impl Iterator<Item = &BlockDef> {
+        self.defs.iter()
+    }
+
+    pub fn state(&self, state: StateId) -> &BlockState {
+        &self.states[state as usize]
+    }
+
+    pub fn properties(&self, state: StateId) -> StateProperties {
+        self.state(state).properties
+    }
+
+    pub fn default_state(&self, id: BlockId) -> StateId {
+        self.default_states[id as usize]
+    }
+
+    /// The default state of the block with the given name
+    pub fn default_state_of(&self, name: &str) -> Option<StateId> {
+        self.id(name).map(|id| self.default_state(id))
+    }
+
+    /// The state of a block with the given properties, the ones the block does not have are ignored
+    pub fn state_id(&self, id: BlockId, properties: StateProperties) -> StateId {
+        let properties = properties.restrict(&self.block_def(id).properties);
+        self.state_ids[&BlockState { block: id, properties }]
+    }
+
+    /// The same block as state with other properties
+    pub fn with_properties(&self, state: StateId, properties: StateProperties) -> StateId {
+        self.state_id(self.state(state).block, properties)
+    }
+
+    pub fn behaviour(&self, state: StateId) -> &BlockBehaviour {
+        &self.behaviours[self.state(state).block as usize]
+    }
+
+    pub fn register_behaviour(&mut self, name: &str, behaviour: BlockBehaviour) {
+        match self.id(name) {
+            Some(id) => self.behaviours[id as usize] = behaviour,
+            None => warn!("Behaviour registered for unknown block {}.", name),
+        }
+    }
+
+    pub fn is_transparent(&self, state: StateId) -> bool {
+        self.def(state).transparent
+    }
+
+    pub fn render_class(&self, state: StateId) -> RenderClass {
+        self.def(state).render_class
+    }
+
+    /// Minimum and maximum uv of a face of the block in the block atlas
+    pub fn face_uv(&self, state: StateId, face: Face) -> [Vec2; 2] {
+        self.face_uvs[state as usize][face as usize]
+    }
+
+    /// Looks up the atlas uvs of every face texture, keyed by the texture path
+    pub fn set_texture_uvs(&mut self, uvs: &HashMap<String, [Vec2; 2]>) {
+        for (index, state) in self.states.iter().enumerate() {
+            let def = &self.defs[state.block as usize];
+            for face in Face::ALL {
+                // blocks lying on their side show their textures rotated onto other faces
+                if let Some(texture) = def.face_texture(state.properties.texture_face(face)) {
+                    match uvs.get(texture) {
+                        Some(uv) => self.face_uvs[index][face as usize] = *uv,
+                        None => warn!("Texture {} of block {} is not in the atlas.", texture, def.name),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        let mut registry = Self::from_json(include_str!("../assets/blocks.json"));
+        crate::behaviour::register_behaviours(&mut registry);
+        registry
+    }
+}
+
+/// A snapshot of the registry which background jobs can hold on to
+#[derive(Clone)]
+pub struct SharedRegistry(pub Arc<BlockRegistry>);
+
+/// Takes a new snapshot of the registry whenever it changes, like when the atlas uvs arrive
+pub fn share_registry(
+    mut commands: Commands,
+    registry: Res<BlockRegistry>,
+) {
+    if registry.is_changed() {
+        commands.insert_resource(SharedRegistry(Arc::new(registry.clone())));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,71 @@
+use bevy::{
+    app::AppExit,
+    prelude::*
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    weather::Weather,
+    world_time::WorldTime
+};
+
+use std::{fs, path::Path};
+
+/// Where the state of the world is kept between runs
+const SAVE_PATH: &str = "saves/world.json";
+/// The world is saved every 30 seconds of simulation
+const AUTOSAVE_TICKS: u64 = 600;
+
+/// The state of the world which is kept between runs, the terrain comes back from the seed
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct SaveFile {
+    pub time: WorldTime,
+    pub weather: Weather,
+}
+
+impl SaveFile {
+    /// Reads the save file, a new world starts when there is none or it can not be read
+    pub fn load() -> Self {
+        let json = match fs::read_to_string(SAVE_PATH) {
+            Ok(json) => json,
+            Err(_) => return Self::default(),
+        };
+        serde_json::from_str(&json).unwrap_or_else(|error| {
+            warn!("Ignoring unreadable save file {}: {}", SAVE_PATH, error);
+            Self::default()
+        })
+    }
+
+    pub fn write(&self) {
+        let result = Path::new(SAVE_PATH).parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| {
+                let json = serde_json::to_string_pretty(self).map_err(std::io::Error::from)?;
+                fs::write(SAVE_PATH, json)
+            });
+        if let Err(error) = result {
+            error!("Could not save the world to {}: {}", SAVE_PATH, error);
+        }
+    }
+}
+
+/// Saves the world every AUTOSAVE_TICKS ticks and when the app exits
+///
+/// It runs in the last stage, after every system which may send AppExit, since there is
+/// no frame after the one that sends it
+pub fn save_world(
+    mut exit: EventReader<AppExit>,
+    time: Res<WorldTime>,
+    weather: Res<Weather>,
+    mut last_save: Local<Option<u64>>,
+) {
+    let exiting = exit.iter().count() > 0;
+    // the loaded world counts as saved
+    let last = *last_save.get_or_insert(time.tick);
+    if !exiting && time.tick < last + AUTOSAVE_TICKS && time.tick >= last {
+        return;
+    }
+    *last_save = Some(time.tick);
+    SaveFile { time: time.clone(), weather: weather.clone() }.write();
+}
\ No newline at end of file
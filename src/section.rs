@@ -0,0 +1,237 @@
+use crate::{
+    registry::{StateId, AIR},
+    world::CHUNK_SIZE
+};
+
+use std::mem::size_of;
+
+/// Number of blocks in a 16x16x16 section
+pub const SECTION_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// The block states of a 16x16x16 section, as a palette of the states it contains
+/// and a bit-packed index into the palette for every block
+///
+/// Indices never straddle two words, the bits left at the end of a word are unused
+#[derive(Clone, Debug)]
+pub struct Section {
+    palette: Vec<StateId>,
+    /// Bits per index, 0 while the palette holds a single state and no indices are stored
+    bits: u32,
+    data: Vec<u64>,
+    /// Number of blocks which are not air, the section needs no work at all when it is 0
+    non_air: u16,
+}
+
+impl Default for Section {
+    fn default() -> Self {
+        Self::filled(AIR)
+    }
+}
+
+impl Section {
+    /// A section where every block is state
+    pub fn filled(state: StateId) -> Self {
+        Self {
+            palette: vec![state],
+            bits: 0,
+            data: Vec::new(),
+            non_air: if state == AIR { 0 } else { SECTION_VOLUME as u16 },
+        }
+    }
+
+    /// Index of a block from its position inside the section
+    pub fn index(x: usize, y: usize, z: usize) -> usize {
+        let size = CHUNK_SIZE as usize;
+        (y * size + z) * size + x
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.non_air == 0
+    }
+
+    pub fn get(&self, index: usize) -> StateId {
+        if self.bits == 0 {
+            return self.palette[0];
+        }
+        self.palette[self.palette_index(index)]
+    }
+
+    /// Replaces the block at index and returns the previous state
+    pub fn set(&mut self, index: usize, state: StateId) -> StateId {
+        let old = self.get(index);
+        if old == state {
+            return old;
+        }
+        let entry = match self.palette.iter().position(|entry| *entry == state) {
+            Some(entry) => entry,
+            None => {
+                self.palette.push(state);
+                if self.palette.len() > 1 << self.bits {
+                    self.grow();
+                }
+                // a new state is always the last entry, also after growing
+                self.palette.len() - 1
+            }
+        };
+        self.write(index, entry);
+        match (old == AIR, state == AIR) {
+            (true, false) => self.non_air += 1,
+            (false, true) => self.non_air -= 1,
+            _ => {},
+        }
+        old
+    }
+
+    /// Bytes used by the section, including its heap allocations
+    pub fn memory(&self) -> usize {
+        size_of::<Self>()
+            + self.palette.capacity() * size_of::<StateId>()
+            + self.data.capacity() * size_of::<u64>()
+    }
+
+    fn per_word(bits: u32) -> usize {
+        (u64::BITS / bits) as usize
+    }
+
+    fn palette_index(&self, index: usize) -> usize {
+        let per_word = Self::per_word(self.bits);
+        let shift = (index % per_word) as u32 * self.bits;
+        ((self.data[index / per_word] >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+
+    fn write(&mut self, index: usize, entry: usize) {
+        let per_word = Self::per_word(self.bits);
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        let word = &mut self.data[index / per_word];
+        *word = (*word & !mask) | ((entry as u64) << shift);
+    }
+
+    /// Makes room for the state which was just pushed to the palette, the states
+    /// no block uses anymore are dropped first and the indices only widen if that is not enough
+    fn grow(&mut self) {
+        let mut blocks = Vec::with_capacity(SECTION_VOLUME);
+        for index in 0..SECTION_VOLUME {
+            blocks.push(self.get(index));
+        }
+        let added = self.palette[self.palette.len() - 1];
+        let mut palette: Vec<StateId> = Vec::new();
+        for state in blocks.iter().copied().chain([added]) {
+            if !palette.contains(&state) {
+                palette.push(state);
+            }
+        }
+        // enough bits to index every entry, with at least one
+        let bits = (usize::BITS - (palette.len() - 1).leading_zeros()).max(1);
+        let per_word = Self::per_word(bits);
+        self.palette = palette;
+        self.bits = bits;
+        self.data = vec![0; (SECTION_VOLUME + per_word - 1) / per_word];
+        for (index, state) in blocks.into_iter().enumerate() {
+            let entry = self.palette.iter().position(|entry| *entry == state).unwrap_or(0);
+            self.write(index, entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the section takes as a plain array of states, like the ndarray it replaced
+    const RAW_MEMORY: usize = SECTION_VOLUME * size_of::<StateId>();
+
+    #[test]
+    fn filled_section_stores_no_indices() {
+        let section = Section::filled(7);
+        assert_eq!(section.bits, 0);
+        assert!(section.data.is_empty());
+        assert!((0..SECTION_VOLUME).all(|index| section.get(index) == 7));
+        assert!(!section.is_empty());
+        assert!(Section::default().is_empty());
+    }
+
+    #[test]
+    fn set_and_get_round_trip() {
+        let mut section = Section::default();
+        for index in 0..SECTION_VOLUME {
+            section.set(index, (index % 37) as StateId);
+        }
+        for index in 0..SECTION_VOLUME {
+            assert_eq!(section.get(index), (index % 37) as StateId, "block {}", index);
+        }
+        assert_eq!(section.set(5, 100), 5);
+        assert_eq!(section.get(5), 100);
+        assert_eq!(section.get(4), 4);
+        assert_eq!(section.get(6), 6);
+    }
+
+    #[test]
+    fn indices_widen_with_the_palette() {
+        let mut section = Section::default();
+        for state in 1..=16 {
+            section.set(state as usize, state);
+            // the palette holds air and the states up to this one
+            assert_eq!(section.bits, StateId::BITS - state.leading_zeros(), "after state {}", state);
+        }
+        for state in 1..=16 {
+            assert_eq!(section.get(state as usize), state);
+        }
+        assert_eq!(section.get(0), AIR);
+        assert_eq!(section.get(SECTION_VOLUME - 1), AIR);
+    }
+
+    #[test]
+    fn growth_drops_unused_states_before_widening() {
+        let mut section = Section::default();
+        section.set(0, 1);
+        section.set(0, AIR);
+        section.set(0, 2);
+        assert_eq!(section.palette, vec![AIR, 2]);
+        assert_eq!(section.bits, 1);
+        assert_eq!(section.get(0), 2);
+    }
+
+    #[test]
+    fn counts_the_blocks_which_are_not_air() {
+        let mut section = Section::default();
+        section.set(0, 1);
+        section.set(1, 1);
+        section.set(1, 2);
+        assert_eq!(section.non_air, 2);
+        section.set(0, AIR);
+        section.set(1, AIR);
+        assert!(section.is_empty());
+    }
+
+    #[test]
+    fn typical_sections_use_less_memory_than_raw_arrays() {
+        let size = CHUNK_SIZE as usize;
+        // stone below, dirt and grass on top, air above, with a few ores in the stone
+        let mut surface = Section::default();
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    let state = match y {
+                        0..=9 if (x * 7 + y * 3 + z * 5) % 61 == 0 => 4,
+                        0..=9 => 2,
+                        10..=11 => 6,
+                        12 => 7,
+                        _ => AIR,
+                    };
+                    surface.set(Section::index(x, y, z), state);
+                }
+            }
+        }
+        let underground = Section::filled(2);
+        let sky = Section::default();
+        for section in [&surface, &underground, &sky] {
+            assert!(
+                section.memory() * 4 <= RAW_MEMORY,
+                "{} bytes packed against {} raw",
+                section.memory(),
+                RAW_MEMORY
+            );
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,94 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+
+#import bevy_pbr::mesh_functions
+
+#import bevy_atmosphere::math
+
+struct Fog {
+    start: f32,
+    end: f32,
+    tint: vec4<f32>,
+}
+
+// how much the colour of the sky tints the lit terrain
+let SKY_AMBIENT: f32 = 0.2;
+
+@group(1) @binding(0)
+var sky_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var sky_sampler: sampler;
+@group(1) @binding(2)
+var<uniform> fog: Fog;
+@group(1) @binding(3)
+var atlas_texture: texture_2d<f32>;
+@group(1) @binding(4)
+var atlas_sampler: sampler;
+@group(1) @binding(5)
+var<uniform> alpha_cutoff: f32;
+
+struct Vertex {
+    @location(0)
+        position: vec3<f32>,
+    @location(1)
+        normal: vec3<f32>,
+    @location(2)
+        uv: vec2<f32>,
+    @location(3)
+        color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position)
+        clip_position: vec4<f32>,
+    @location(0)
+        world_position: vec4<f32>,
+    @location(1)
+        uv: vec2<f32>,
+    @location(2)
+        color: vec4<f32>,
+    @location(3)
+        world_normal: vec3<f32>,
+}
+
+fn sample_sky(direction: vec3<f32>) -> vec3<f32> {
+    let resolution = f32(textureDimensions(sky_texture).y);
+    // without mipmaps the level is always 0, which also allows sampling inside branches
+    return textureSampleLevel(sky_texture, sky_sampler, sky_face_uv(direction, resolution), 0.0).rgb;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.clip_position = mesh_position_world_to_clip(out.world_position);
+    out.uv = vertex.uv;
+    out.color = vertex.color;
+    out.world_normal = mesh_normal_local_to_world(vertex.normal);
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // the vertex colour holds the light of the block
+    var color = textureSample(atlas_texture, atlas_sampler, in.uv) * in.color;
+    if (color.a < alpha_cutoff) {
+        discard;
+    }
+
+    // the sky the face looks at tints it, the vertex colour keeps the caves dark
+    let ambient = sample_sky(normalize(in.world_normal));
+    let tint = ambient / max(max(ambient.r, ambient.g), max(ambient.b, 1e-3));
+    color = vec4<f32>(color.rgb * mix(vec3<f32>(1f), tint, SKY_AMBIENT), color.a);
+
+    let offset = in.world_position.xyz - view.world_position;
+    let amount = smoothstep(fog.start, fog.end, length(offset));
+    if (amount > 0.0) {
+        // the fog takes the colour of the sky along the horizon behind the fragment
+        let horizon = normalize(vec3<f32>(offset.x, 0.0, offset.z) + vec3<f32>(1e-4, 0.0, 0.0));
+        let sky = sample_sky(horizon);
+        let fog_color = mix(sky, fog.tint.rgb, fog.tint.a);
+        color = vec4<f32>(mix(color.rgb, fog_color, amount), color.a);
+    }
+    return color;
+}
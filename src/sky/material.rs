@@ -1,4 +1,5 @@
 use bevy::{prelude::*, reflect::TypeUuid, render::render_resource::{AsBindGroup, ShaderRef, CompareFunction, ShaderType}};
+use serde::{Deserialize, Serialize};
 
 pub const ATMOSPHERE_MAIN_SHADER_HANDLE: HandleUntyped =
     HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 05132991701789555342);
@@ -6,11 +7,16 @@ pub const ATMOSPHERE_MATH_SHADER_HANDLE: HandleUntyped =
     HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 07843425155352921761);
 pub const ATMOSPHERE_TYPES_SHADER_HANDLE: HandleUntyped =
     HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 09615256157423613453);
+pub const SKY_CUBEMAP_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 04418275530967715093);
 
 /// Controls the appearance of the sky
-#[derive(ShaderType, AsBindGroup, Debug, TypeUuid, Clone, Copy)]
+///
+/// It reads from and writes to JSON, the fields left out take their default value
+#[derive(ShaderType, AsBindGroup, Serialize, Deserialize, Debug, TypeUuid, Clone, Copy)]
 #[uuid = "a57878c4-569e-4511-be7c-b0e5b2c983e2"]
 #[uniform(0, Atmosphere)]
+#[serde(default)]
 pub struct Atmosphere {
     /// Ray Origin (Default: (0.0, 6372e3, 0.0))
     pub ray_origin: Vec3,
@@ -32,6 +38,32 @@ pub struct Atmosphere {
     pub mie_scale_height: f32,
     /// Mie Scattering Preferred Direction (Default: 0.758)
     pub mie_direction: f32,
+    /// Moon Position (Default: (0.0, -1.0, -1.0))
+    pub moon_position: Vec3,
+    /// Moon Intensity, brightness of the lit part of the moon disc (Default: 1.0)
+    pub moon_intensity: f32,
+    /// Moon Phase, the part of the lunar cycle that has passed, 0 is a full moon and 0.5 a new moon (Default: 0.0)
+    pub moon_phase: f32,
+    /// Moon Angular Radius in radians (Default: 0.03)
+    pub moon_radius: f32,
+    /// Star Density, the part of the sky cells that hold a star (Default: 0.04)
+    pub star_density: f32,
+    /// Star Twinkle, how much the brightness of the stars flickers, from 0 to 1 (Default: 0.5)
+    pub star_twinkle: f32,
+    /// Star Rotation around the celestial pole, the x axis, in radians (Default: 0.0)
+    pub star_rotation: f32,
+    /// Time in seconds, animates the sky (Default: 0.0)
+    pub time: f32,
+    /// Cloud Height above the planet surface (Default: 2.5e3)
+    pub cloud_height: f32,
+    /// Cloud Coverage, the part of the sky hidden by clouds, from 0 to 1 (Default: 0.45)
+    pub cloud_coverage: f32,
+    /// Cloud Scale, the width of the cloud shapes (Default: 3e3)
+    pub cloud_scale: f32,
+    /// Wind, the velocity the clouds drift with along x and z (Default: (12.0, 5.0))
+    pub wind: Vec2,
+    /// Lightning, brightness of the flash lighting up the sky and the clouds, from 0 to 1 (Default: 0.0)
+    pub lightning: f32,
 }
 
 impl From<&Atmosphere> for Atmosphere {
@@ -53,6 +85,19 @@ impl Default for Atmosphere {
             mie_coefficient: 21e-6,
             mie_scale_height: 1.2e3,
             mie_direction: 0.758,
+            moon_position: Vec3::new(0.0, -1.0, -1.0),
+            moon_intensity: 1.0,
+            moon_phase: 0.0,
+            moon_radius: 0.03,
+            star_density: 0.04,
+            star_twinkle: 0.5,
+            star_rotation: 0.0,
+            time: 0.0,
+            cloud_height: 2.5e3,
+            cloud_coverage: 0.45,
+            cloud_scale: 3e3,
+            wind: Vec2::new(12.0, 5.0),
+            lightning: 0.0,
         }
     }
 }
@@ -88,3 +133,50 @@ impl Material for Atmosphere {
             Ok(())
     }
 }
+
+
+/// Draws the sky from the baked cubemap, whose six faces lie side by side in one image
+///
+/// Only the scattering is baked, the moon, the stars, the clouds and the lightning change
+/// or are too small for the cubemap and are drawn for every pixel on top of it
+#[derive(AsBindGroup, Debug, TypeUuid, Clone)]
+#[uuid = "d0e7f4b2-2c0a-4f43-8f0e-5b8f1e6c3a97"]
+pub struct SkyCubemapMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub cubemap: Handle<Image>,
+    #[uniform(2)]
+    pub atmosphere: Atmosphere,
+}
+
+impl Material for SkyCubemapMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SKY_CUBEMAP_SHADER_HANDLE.typed().into()
+    }
+
+    fn vertex_shader() -> ShaderRef {
+        SKY_CUBEMAP_SHADER_HANDLE.typed().into()
+    }
+
+    fn specialize(
+            _pipeline: &bevy::pbr::MaterialPipeline<Self>,
+            descriptor: &mut bevy::render::render_resource::RenderPipelineDescriptor,
+            layout: &bevy::render::mesh::MeshVertexBufferLayout,
+            _key: bevy::pbr::MaterialPipelineKey<Self>,
+        ) -> Result<(), bevy::render::render_resource::SpecializedMeshPipelineError> {
+
+            let vertex_layout = layout.get_layout(&[
+                Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+                Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+                Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            ])?;
+
+            descriptor.vertex.buffers = vec![vertex_layout];
+
+            if let Some (depth_stencil_state) = &mut descriptor.depth_stencil {
+                depth_stencil_state.depth_compare = CompareFunction::GreaterEqual;
+                depth_stencil_state.depth_write_enabled = false;
+            }
+            Ok(())
+    }
+}
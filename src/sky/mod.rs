@@ -1,22 +1,97 @@
 pub mod material;
+pub mod preset;
+pub mod scattering;
 
 use bevy::{
     pbr::NotShadowCaster,
     prelude::*, asset::load_internal_asset,
+    render::{
+        camera::{
+            RenderTarget,
+            Viewport
+        },
+        render_resource::{
+            Extent3d,
+            TextureDescriptor,
+            TextureDimension,
+            TextureFormat,
+            TextureUsages
+        },
+        texture::ImageSampler,
+        view::{
+            NoFrustumCulling,
+            RenderLayers
+        }
+    },
+    core_pipeline::clear_color::ClearColorConfig,
+    transform::TransformSystem,
+};
+use std::{
+    f32::consts::FRAC_PI_2,
+    ops::Deref
 };
-use std::ops::Deref;
 use material::*;
+use preset::*;
 
 /// Sets up the atmosphere and the systems that control it
 ///
-/// Follows the first camera it finds
-#[derive(Default)]
+/// The sky is drawn around every camera marked with AtmosphereCamera
 pub struct AtmospherePlugin {
+    /// Width and height in pixels of each face of the cubemap the sky is baked into
     pub resolution: u32,
 }
 
+impl Default for AtmospherePlugin {
+    fn default() -> Self {
+        Self { resolution: 128 }
+    }
+}
+
+/// Only the cameras baking the cubemap see this layer, it holds the scattering sphere
+pub const SKY_BAKE_LAYER: u8 = 31;
+/// The cubemap is baked at most this often while the atmosphere keeps changing, in seconds
+const BAKE_INTERVAL: f64 = 0.25;
+
+/// The sky baked into a cubemap, its six faces lie side by side in one image in the order
+/// +x, -x, +y, -y, +z, -z, it is drawn as the background and lights the terrain
+pub struct SkyCubemap {
+    pub image: Handle<Image>,
+    pub resolution: u32,
+    /// When the cubemap was baked last, in seconds since startup
+    last_bake: f64,
+    /// Whether the atmosphere changed since the last bake
+    outdated: bool,
+}
+
+struct CubemapResolution(u32);
+
+/// Marks the cameras which bake the faces of the cubemap
+#[derive(Component)]
+pub struct SkyBakeCamera;
+
+/// Marks the cameras the sky is drawn around, each of them gets a sky box of its own which follows it
+///
+/// The sky box takes the RenderLayers of the camera when it has some, so split views can keep
+/// their skies apart
+#[derive(Component, Default)]
+pub struct AtmosphereCamera;
+
+/// The sphere showing the baked sky around one camera
+#[derive(Component)]
+pub struct SkyBox {
+    camera: Entity,
+}
+
+// the mesh and the material shared by the sky boxes
+struct SkyBoxAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<SkyCubemapMaterial>,
+}
+
 /// Label for startup system that prepares skyboxes
 pub const ATMOSPHERE_INIT: &'static str = "ATMOSPHERE_INIT";
+/// Label for the system that fades the atmosphere from one preset into another
+pub const ATMOSPHERE_TRANSITION: &'static str = "ATMOSPHERE_TRANSITION";
 
 impl Plugin for AtmospherePlugin {
     fn build(&self, app: &mut App) {
@@ -41,71 +116,271 @@ impl Plugin for AtmospherePlugin {
             Shader::from_wgsl
         );
 
+        load_internal_asset!(
+            app,
+            SKY_CUBEMAP_SHADER_HANDLE,
+            "shaders/cubemap.wgsl",
+            Shader::from_wgsl
+        );
+
         app.add_plugin(MaterialPlugin::<Atmosphere>::default());
-        
+        app.add_plugin(MaterialPlugin::<SkyCubemapMaterial>::default());
+
+        app.insert_resource(CubemapResolution(self.resolution.max(1)));
+
+        let presets = AtmospherePresets::load(AtmospherePresets::folder(app));
+        let earth = presets.get(DEFAULT_PRESET).copied().unwrap_or_default();
+        app.insert_resource(AtmosphereTransition::new(DEFAULT_PRESET, earth));
+        app.insert_resource(presets);
         app.add_startup_system_to_stage(StartupStage::PostStartup, atmosphere_init.label(ATMOSPHERE_INIT));
 
-        app.add_system(atmosphere_dynamic_sky);
+        app.add_system(advance_atmosphere_transition.label(ATMOSPHERE_TRANSITION).before("atmosphere_dynamic_sky"));
+        app.add_system(atmosphere_dynamic_sky.label("atmosphere_dynamic_sky"));
+        app.add_system(spawn_sky_boxes);
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            follow_atmosphere_cameras.after(TransformSystem::TransformPropagate)
+        );
+        app.add_system(bake_sky_cubemap.after("atmosphere_dynamic_sky"));
+        app.add_system(sky_ambient_light);
     }
 }
 
+// the direction and the up direction of the camera of each face of the cubemap
+const CUBEMAP_FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::Y),
+    (Vec3::NEG_X, Vec3::Y),
+    (Vec3::Y, Vec3::Z),
+    (Vec3::NEG_Y, Vec3::NEG_Z),
+    (Vec3::Z, Vec3::Y),
+    (Vec3::NEG_Z, Vec3::Y),
+];
+
+fn cubemap_image(resolution: u32) -> Image {
+    let size = Extent3d {
+        width: resolution * 6,
+        height: resolution,
+        ..default()
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("sky_cubemap"),
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        // the few pixels of a face are stretched over the whole screen
+        sampler_descriptor: ImageSampler::linear(),
+        ..default()
+    };
+    image.resize(size);
+    image
+}
+
+// the scattering sphere is only seen by the cameras baking the cubemap, which the sky boxes draw
 fn atmosphere_init(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
     mut sky_materials: ResMut<Assets<Atmosphere>>,
+    mut cubemap_materials: ResMut<Assets<SkyCubemapMaterial>>,
     atmosphere: Option<Res<Atmosphere>>,
+    resolution: Res<CubemapResolution>,
 ) {
+    let resolution = resolution.0;
     let atmosphere = match atmosphere {
         None => Atmosphere::default(),
         Some(c) => c.deref().clone(),
     };
 
-    let atmosphere = sky_materials.add(atmosphere);
+    let sky_material = sky_materials.add(atmosphere);
+    let image = images.add(cubemap_image(resolution));
+    let sphere = meshes.add(Mesh::from(shape::Icosphere {
+        radius: -100.0,
+        subdivisions: 2,
+    }));
 
     commands
         .spawn_bundle(MaterialMeshBundle {
-            mesh: meshes.add(Mesh::from(shape::Icosphere {
-                radius: -100.0,
-                subdivisions: 2,
-            })),
-            material: atmosphere,
+            mesh: sphere.clone(),
+            material: sky_material,
             ..Default::default()
         })
         .insert(Transform::from_xyz(0.0, 0.0, 0.0))
         .insert(NotShadowCaster)
-        .insert(Name::new("Sky Box"));
+        .insert(RenderLayers::layer(SKY_BAKE_LAYER))
+        .insert(Name::new("Sky Scattering"))
+        .with_children(|parent| {
+            for (index, (direction, up)) in CUBEMAP_FACES.into_iter().enumerate() {
+                // the first camera clears the whole image, the others draw over it
+                let clear_color = if index == 0 { ClearColorConfig::Custom(Color::BLACK) } else { ClearColorConfig::None };
+                parent.spawn_bundle(Camera3dBundle {
+                    camera: Camera {
+                        target: RenderTarget::Image(image.clone()),
+                        viewport: Some(Viewport {
+                            physical_position: UVec2::new(index as u32 * resolution, 0),
+                            physical_size: UVec2::new(resolution, resolution),
+                            ..default()
+                        }),
+                        priority: index as isize - 6,
+                        is_active: false,
+                        ..default()
+                    },
+                    camera_3d: Camera3d {
+                        clear_color,
+                        ..default()
+                    },
+                    projection: PerspectiveProjection {
+                        fov: FRAC_PI_2,
+                        aspect_ratio: 1.0,
+                        ..default()
+                    }.into(),
+                    transform: Transform::IDENTITY.looking_at(direction, up),
+                    ..default()
+                })
+                .insert(RenderLayers::layer(SKY_BAKE_LAYER))
+                .insert(UiCameraConfig { show_ui: false })
+                .insert(SkyBakeCamera);
+            }
+        });
+
+    commands.insert_resource(SkyBoxAssets {
+        mesh: sphere,
+        material: cubemap_materials.add(SkyCubemapMaterial { cubemap: image.clone(), atmosphere }),
+    });
+    commands.insert_resource(SkyCubemap {
+        image,
+        resolution,
+        last_bake: f64::NEG_INFINITY,
+        outdated: true,
+    });
 }
 
-#[derive(Default)]
-pub struct AtmosphereTransform {
-    pub translation: Vec3
+/// Gives every AtmosphereCamera a sky box and removes the sky boxes of the cameras that are gone
+fn spawn_sky_boxes(
+    mut commands: Commands,
+    assets: Option<Res<SkyBoxAssets>>,
+    cameras: Query<(Entity, Option<&RenderLayers>), With<AtmosphereCamera>>,
+    sky_boxes: Query<(Entity, &SkyBox)>,
+) {
+    let assets = match assets {
+        Some(assets) => assets,
+        None => return,
+    };
+    for (entity, sky_box) in &sky_boxes {
+        if cameras.get(sky_box.camera).is_err() {
+            commands.entity(entity).despawn();
+        }
+    }
+    for (camera, layers) in &cameras {
+        if sky_boxes.iter().any(|(_, sky_box)| sky_box.camera == camera) {
+            continue;
+        }
+        let mut sky_box = commands.spawn_bundle(MaterialMeshBundle {
+            mesh: assets.mesh.clone(),
+            material: assets.material.clone(),
+            ..Default::default()
+        });
+        sky_box
+            .insert(NotShadowCaster)
+            .insert(NoFrustumCulling)
+            .insert(SkyBox { camera })
+            .insert(Name::new("Sky Box"));
+        if let Some(layers) = layers {
+            sky_box.insert(*layers);
+        }
+    }
 }
 
-impl AtmosphereTransform {
-    pub fn update(&mut self, location: Vec3) {
-        let (x, z) = (location.x, location.z);
-        self.translation = Vec3::new(x, 0.0, z);
+/// Centers the sky boxes on their cameras once the cameras have moved for the frame,
+/// the sky boxes do not turn with the cameras since the direction picks the colour of the sky
+fn follow_atmosphere_cameras(
+    cameras: Query<&GlobalTransform, With<AtmosphereCamera>>,
+    mut sky_boxes: Query<(&SkyBox, &mut Transform, &mut GlobalTransform), Without<AtmosphereCamera>>,
+) {
+    for (sky_box, mut transform, mut global_transform) in &mut sky_boxes {
+        if let Ok(camera) = cameras.get(sky_box.camera) {
+            transform.translation = camera.translation();
+            *global_transform = GlobalTransform::from(*transform);
+        }
     }
 }
 
 fn atmosphere_dynamic_sky(
-    mut atmosphere_transform: Query<(&mut Transform, &Name)>,
-    global_transform: Res<AtmosphereTransform>,
     global_atmosphere: Res<Atmosphere>,
     atmosphere_query: Query<&Handle<Atmosphere>>,
     mut atmospheres: ResMut<Assets<Atmosphere>>,
+    sky_box_assets: Option<Res<SkyBoxAssets>>,
+    mut cubemap_materials: ResMut<Assets<SkyCubemapMaterial>>,
 ) {
-    if let Some((mut transform, name)) = atmosphere_transform.iter_mut().next() {
-        if name.as_str() == "Sky Box" {
-            transform.translation = global_transform.translation;
-        }
-    }
-    
     if global_atmosphere.is_changed() {
         if let Some(atmosphere_handle) = atmosphere_query.iter().next() {
             if let Some(atmosphere) = atmospheres.get_mut(atmosphere_handle) {
                 *atmosphere = global_atmosphere.deref().clone();
             }
         }
+        // the sky boxes draw the moon, the stars and the clouds from it
+        if let Some(assets) = sky_box_assets {
+            if let Some(material) = cubemap_materials.get_mut(&assets.material) {
+                material.atmosphere = *global_atmosphere;
+            }
+        }
+    }
+}
+
+
+/// Bakes the cubemap again when the atmosphere changed, at most once every BAKE_INTERVAL
+/// since the daylight cycle changes it every frame
+fn bake_sky_cubemap(
+    time: Res<Time>,
+    atmosphere: Res<Atmosphere>,
+    cubemap: Option<ResMut<SkyCubemap>>,
+    mut cameras: Query<&mut Camera, With<SkyBakeCamera>>,
+) {
+    let mut cubemap = match cubemap {
+        Some(cubemap) => cubemap,
+        None => return,
+    };
+    if atmosphere.is_changed() {
+        cubemap.outdated = true;
+    }
+    let now = time.seconds_since_startup();
+    let bake = cubemap.outdated && now - cubemap.last_bake >= BAKE_INTERVAL;
+    if bake {
+        cubemap.outdated = false;
+        cubemap.last_bake = now;
+    }
+    for mut camera in &mut cameras {
+        if camera.is_active != bake {
+            camera.is_active = bake;
+        }
+    }
+}
+
+/// Gives the ambient light the colour of the sky, averaged over the zenith and the horizon around,
+/// its brightness is left to the other systems
+fn sky_ambient_light(
+    atmosphere: Res<Atmosphere>,
+    mut ambient: ResMut<AmbientLight>,
+) {
+    if !atmosphere.is_changed() {
+        return;
     }
+    let directions = [
+        Vec3::Y,
+        Vec3::new(1.0, 0.2, 0.0),
+        Vec3::new(-1.0, 0.2, 0.0),
+        Vec3::new(0.0, 0.2, 1.0),
+        Vec3::new(0.0, 0.2, -1.0),
+    ];
+    let color = directions.iter()
+        .fold(Vec3::ZERO, |sum, direction| sum + atmosphere.sky_color(*direction))
+        / directions.len() as f32;
+    let color = color / color.max_element().max(1e-3);
+    ambient.color = Color::rgb_linear(color.x, color.y, color.z);
 }
@@ -0,0 +1,251 @@
+use bevy::{
+    asset::{
+        AssetServerSettings,
+        FileAssetIo
+    },
+    prelude::*
+};
+use std::{
+    fs,
+    ops::{Add, Mul},
+    path::{Path, PathBuf}
+};
+
+use super::material::Atmosphere;
+
+/// Where the presets are read from inside the asset folder, one JSON file per preset, named after the file
+pub const PRESETS_PATH: &str = "atmospheres";
+/// The preset the sky starts with, the default atmosphere when there is no file for it
+pub const DEFAULT_PRESET: &str = "earth";
+/// Seconds it takes the sky to turn into the next preset
+const PRESET_FADE_SECONDS: f32 = 10.0;
+
+// written so that it gives back a and b exactly at 0 and 1
+fn lerp<T: Mul<f32, Output = T> + Add<Output = T>>(a: T, b: T, t: f32) -> T {
+    a * (1.0 - t) + b * t
+}
+
+impl Atmosphere {
+    /// Eases the parameters of a preset towards another, the sun, the moon, the stars, the time
+    /// and the lightning stay those of self
+    pub fn lerp(&self, other: &Atmosphere, t: f32) -> Atmosphere {
+        Atmosphere {
+            ray_origin: lerp(self.ray_origin, other.ray_origin, t),
+            sun_intensity: lerp(self.sun_intensity, other.sun_intensity, t),
+            planet_radius: lerp(self.planet_radius, other.planet_radius, t),
+            atmosphere_radius: lerp(self.atmosphere_radius, other.atmosphere_radius, t),
+            rayleigh_coefficient: lerp(self.rayleigh_coefficient, other.rayleigh_coefficient, t),
+            rayleigh_scale_height: lerp(self.rayleigh_scale_height, other.rayleigh_scale_height, t),
+            mie_coefficient: lerp(self.mie_coefficient, other.mie_coefficient, t),
+            mie_scale_height: lerp(self.mie_scale_height, other.mie_scale_height, t),
+            mie_direction: lerp(self.mie_direction, other.mie_direction, t),
+            moon_intensity: lerp(self.moon_intensity, other.moon_intensity, t),
+            moon_radius: lerp(self.moon_radius, other.moon_radius, t),
+            star_density: lerp(self.star_density, other.star_density, t),
+            star_twinkle: lerp(self.star_twinkle, other.star_twinkle, t),
+            cloud_height: lerp(self.cloud_height, other.cloud_height, t),
+            cloud_coverage: lerp(self.cloud_coverage, other.cloud_coverage, t),
+            cloud_scale: lerp(self.cloud_scale, other.cloud_scale, t),
+            wind: lerp(self.wind, other.wind, t),
+            ..*self
+        }
+    }
+
+    /// Takes the parameters of a preset, keeping the sun, the moon, the stars, the time and the lightning
+    /// which move on their own
+    pub fn apply_preset(&mut self, preset: &Atmosphere) {
+        *self = Atmosphere {
+            sun_position: self.sun_position,
+            moon_position: self.moon_position,
+            moon_phase: self.moon_phase,
+            star_rotation: self.star_rotation,
+            time: self.time,
+            lightning: self.lightning,
+            ..*preset
+        };
+    }
+}
+
+/// The named atmospheres read from PRESETS_PATH, sorted by name
+pub struct AtmospherePresets {
+    presets: Vec<(String, Atmosphere)>,
+}
+
+impl AtmospherePresets {
+    /// The folder of the presets, found the same way the asset server finds the assets,
+    /// so it does not depend on the directory the game is started from
+    pub fn folder(app: &App) -> PathBuf {
+        let asset_folder = app.world.get_resource::<AssetServerSettings>()
+            .map_or("assets", |settings| settings.asset_folder.as_str());
+        FileAssetIo::get_base_path().join(asset_folder).join(PRESETS_PATH)
+    }
+
+    /// Reads every JSON file of the directory, the files which can not be read are skipped
+    /// and the default atmosphere stands in for DEFAULT_PRESET when it is missing
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let mut presets = Vec::new();
+        let entries = fs::read_dir(path).map_err(|error| {
+            warn!("Could not read the atmosphere presets in {}: {}", path.display(), error)
+        });
+        for entry in entries.into_iter().flatten().flatten() {
+            let file = entry.path();
+            if file.extension().map_or(true, |extension| extension != "json") {
+                continue;
+            }
+            let name = match file.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let preset = fs::read_to_string(&file)
+                .map_err(|error| error.to_string())
+                .and_then(|json| serde_json::from_str::<Atmosphere>(&json).map_err(|error| error.to_string()));
+            match preset {
+                Ok(preset) => presets.push((name, preset)),
+                Err(error) => warn!("Ignoring unreadable atmosphere preset {}: {}", file.display(), error),
+            }
+        }
+        if !presets.iter().any(|(name, _)| name == DEFAULT_PRESET) {
+            presets.push((DEFAULT_PRESET.to_string(), Atmosphere::default()));
+        }
+        presets.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Self { presets }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Atmosphere> {
+        self.presets.iter()
+            .find(|(preset, _)| preset == name)
+            .map(|(_, atmosphere)| atmosphere)
+    }
+
+    /// The name of the preset after this one, the first preset follows the last
+    pub fn next(&self, name: &str) -> &str {
+        let index = self.presets.iter()
+            .position(|(preset, _)| preset == name)
+            .map_or(0, |index| (index + 1) % self.presets.len());
+        &self.presets[index].0
+    }
+}
+
+/// The preset the sky is made of, a new preset fades in over a while
+pub struct AtmosphereTransition {
+    /// Name of the preset the sky is turning into
+    pub name: String,
+    from: Atmosphere,
+    to: Atmosphere,
+    /// Seconds since the transition started
+    elapsed: f32,
+    /// Seconds the transition lasts
+    duration: f32,
+}
+
+impl AtmosphereTransition {
+    pub fn new(name: &str, preset: Atmosphere) -> Self {
+        Self {
+            name: name.to_string(),
+            from: preset,
+            to: preset,
+            elapsed: 0.0,
+            duration: 0.0,
+        }
+    }
+
+    /// Starts to turn the sky into another preset, from where the current transition is
+    pub fn start(&mut self, name: &str, preset: Atmosphere, duration: f32) {
+        self.from = self.current();
+        self.to = preset;
+        self.name = name.to_string();
+        self.elapsed = 0.0;
+        self.duration = duration;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// The preset as far as the transition went, it eases in and out
+    pub fn current(&self) -> Atmosphere {
+        if self.is_finished() {
+            return self.to;
+        }
+        let t = self.elapsed / self.duration;
+        self.from.lerp(&self.to, t * t * (3.0 - 2.0 * t))
+    }
+}
+
+/// Moves the transition forward and gives the atmosphere the parameters of the preset
+///
+/// The parameters are only written while the transition runs, other systems may change them on top
+pub fn advance_atmosphere_transition(
+    time: Res<Time>,
+    mut transition: ResMut<AtmosphereTransition>,
+    mut atmosphere: ResMut<Atmosphere>,
+) {
+    let running = !transition.is_finished();
+    if running {
+        transition.elapsed = (transition.elapsed + time.delta_seconds()).min(transition.duration);
+    }
+    if running || transition.is_changed() {
+        atmosphere.apply_preset(&transition.current());
+    }
+}
+
+/// Turns the sky into the next preset when F9 is pressed
+pub fn cycle_atmosphere_preset(
+    keyboard_input: Res<Input<KeyCode>>,
+    presets: Res<AtmospherePresets>,
+    mut transition: ResMut<AtmosphereTransition>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F9) {
+        return;
+    }
+    let name = presets.next(&transition.name).to_string();
+    if let Some(preset) = presets.get(&name) {
+        transition.start(&name, *preset, PRESET_FADE_SECONDS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundled_presets() -> Vec<(PathBuf, Atmosphere)> {
+        let folder = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets").join(PRESETS_PATH);
+        fs::read_dir(&folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|file| file.extension().map_or(false, |extension| extension == "json"))
+            .map(|file| {
+                let json = fs::read_to_string(&file).unwrap();
+                let preset = serde_json::from_str(&json)
+                    .unwrap_or_else(|error| panic!("{} is not an atmosphere: {}", file.display(), error));
+                (file, preset)
+            })
+            .collect()
+    }
+
+    fn same(a: &Atmosphere, b: &Atmosphere) -> bool {
+        serde_json::to_value(a).unwrap() == serde_json::to_value(b).unwrap()
+    }
+
+    #[test]
+    fn bundled_presets_deserialize() {
+        let presets = bundled_presets();
+        assert!(presets.len() >= 4);
+        assert!(presets.iter().any(|(file, _)| file.file_stem().unwrap() == DEFAULT_PRESET));
+    }
+
+    #[test]
+    fn lerp_gives_back_its_endpoints() {
+        let presets = bundled_presets();
+        for (_, a) in &presets {
+            for (_, b) in &presets {
+                assert!(same(&a.lerp(b, 0.0), a));
+                // the sun, the moon, the stars and the time stay those of the first one
+                let mut expected = *a;
+                expected.apply_preset(b);
+                assert!(same(&a.lerp(b, 1.0), &expected));
+            }
+        }
+    }
+}
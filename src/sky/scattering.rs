@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+use std::f32::consts::PI;
+
+use super::material::Atmosphere;
+
+/// Samples along the view ray, the same as ISTEPS in math.wgsl
+const ISTEPS: u32 = 16;
+/// Samples along the ray towards the sun, the same as JSTEPS in math.wgsl
+const JSTEPS: u32 = 8;
+
+/// Ray-sphere intersection with a sphere centered at the origin,
+/// there is no intersection when the first distance is greater than the second
+fn rsi(rd: Vec3, r0: Vec3, sr: f32) -> Vec2 {
+    let a = rd.dot(rd);
+    let b = 2.0 * rd.dot(r0);
+    let c = r0.dot(r0) - sr * sr;
+    let d = b * b - 4.0 * a * c;
+
+    if d < 0.0 {
+        Vec2::new(1e5, -1e5)
+    } else {
+        Vec2::new(
+            (-b - d.sqrt()) / (2.0 * a),
+            (-b + d.sqrt()) / (2.0 * a)
+        )
+    }
+}
+
+impl Atmosphere {
+    /// Light scattered towards the viewer along a view direction, in linear RGB
+    ///
+    /// This is the single scattering integration of render_atmosphere in math.wgsl, step for step
+    pub fn scatter(&self, direction: Vec3) -> Vec3 {
+        // Normalize the ray direction and sun position.
+        let r = direction.normalize();
+        let p_sun = self.sun_position.normalize();
+        let r0 = self.ray_origin;
+
+        // Calculate the step size of the primary ray.
+        let mut p = rsi(r, r0, self.atmosphere_radius);
+        if p.x > p.y {
+            return Vec3::ZERO;
+        }
+        p.y = p.y.min(rsi(r, r0, self.planet_radius).x);
+        let i_step_size = (p.y - p.x) / ISTEPS as f32;
+
+        // Initialize the primary ray time.
+        let mut i_time = 0.0;
+
+        // Initialize accumulators for Rayleigh and Mie scattering.
+        let mut total_rlh = Vec3::ZERO;
+        let mut total_mie = Vec3::ZERO;
+
+        // Initialize optical depth accumulators for the primary ray.
+        let mut i_od_rlh = 0.0;
+        let mut i_od_mie = 0.0;
+
+        // Calculate the Rayleigh and Mie phases.
+        let mu = r.dot(p_sun);
+        let mumu = mu * mu;
+        let g = self.mie_direction;
+        let gg = g * g;
+        let p_rlh = 3.0 / (16.0 * PI) * (1.0 + mumu);
+        let p_mie = 3.0 / (8.0 * PI) * ((1.0 - gg) * (mumu + 1.0)) / ((1.0 + gg - 2.0 * mu * g).powf(1.5) * (2.0 + gg));
+
+        // Sample the primary ray.
+        for _ in 0..ISTEPS {
+            // Calculate the primary ray sample position.
+            let i_pos = r0 + r * (i_time + i_step_size * 0.5);
+
+            // Calculate the height of the sample.
+            let i_height = i_pos.length() - self.planet_radius;
+
+            // Calculate the optical depth of the Rayleigh and Mie scattering for this step.
+            let od_step_rlh = (-i_height / self.rayleigh_scale_height).exp() * i_step_size;
+            let od_step_mie = (-i_height / self.mie_scale_height).exp() * i_step_size;
+
+            // Accumulate optical depth.
+            i_od_rlh += od_step_rlh;
+            i_od_mie += od_step_mie;
+
+            // Calculate the step size of the secondary ray.
+            let j_step_size = rsi(p_sun, i_pos, self.atmosphere_radius).y / JSTEPS as f32;
+
+            // Initialize the secondary ray time.
+            let mut j_time = 0.0;
+
+            // Initialize optical depth accumulators for the secondary ray.
+            let mut j_od_rlh = 0.0;
+            let mut j_od_mie = 0.0;
+
+            // Sample the secondary ray.
+            for _ in 0..JSTEPS {
+                // Calculate the secondary ray sample position.
+                let j_pos = i_pos + p_sun * (j_time + j_step_size * 0.5);
+
+                // Calculate the height of the sample.
+                let j_height = j_pos.length() - self.planet_radius;
+
+                // Accumulate the optical depth.
+                j_od_rlh += (-j_height / self.rayleigh_scale_height).exp() * j_step_size;
+                j_od_mie += (-j_height / self.mie_scale_height).exp() * j_step_size;
+
+                // Increment the secondary ray time.
+                j_time += j_step_size;
+            }
+
+            // Calculate attenuation.
+            let optical_depth = self.mie_coefficient * (i_od_mie + j_od_mie)
+                + self.rayleigh_coefficient * (i_od_rlh + j_od_rlh);
+            let attn = Vec3::new((-optical_depth.x).exp(), (-optical_depth.y).exp(), (-optical_depth.z).exp());
+
+            // Accumulate scattering.
+            total_rlh += od_step_rlh * attn;
+            total_mie += od_step_mie * attn;
+
+            // Increment the primary ray time.
+            i_time += i_step_size;
+        }
+
+        // Calculate and return the final color.
+        self.sun_intensity * (p_rlh * self.rayleigh_coefficient * total_rlh + p_mie * self.mie_coefficient * total_mie)
+    }
+
+    /// Colour of the sky along a view direction as the sky shader draws it, with the same exposure,
+    /// without the moon, the stars and the clouds
+    pub fn sky_color(&self, direction: Vec3) -> Vec3 {
+        let render = self.scatter(direction);
+        Vec3::ONE - Vec3::new((-render.x).exp(), (-render.y).exp(), (-render.z).exp())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zenith_is_blue_under_a_high_sun() {
+        let atmosphere = Atmosphere {
+            sun_position: Vec3::new(0.0, 1.0, 1.0),
+            ..default()
+        };
+        let zenith = atmosphere.scatter(Vec3::Y);
+        assert!(zenith.z > zenith.y && zenith.y > zenith.x, "zenith {:?}", zenith);
+    }
+
+    #[test]
+    fn horizon_is_red_towards_a_setting_sun() {
+        let atmosphere = Atmosphere {
+            sun_position: Vec3::Z,
+            ..default()
+        };
+        let horizon = atmosphere.scatter(Vec3::Z);
+        assert!(horizon.x > horizon.z, "horizon {:?}", horizon);
+    }
+
+    #[test]
+    fn rays_into_the_planet_stay_finite() {
+        let atmosphere = Atmosphere::default();
+        for direction in [Vec3::NEG_Y, Vec3::new(1.0, -0.3, 0.0), Vec3::new(0.0, -0.01, 1.0)] {
+            let color = atmosphere.scatter(direction);
+            assert!(color.is_finite(), "{:?} scatters {:?}", direction, color);
+            let sky = atmosphere.sky_color(direction);
+            assert!(sky.is_finite() && sky.min_element() >= 0.0, "{:?} looks {:?}", direction, sky);
+        }
+    }
+}
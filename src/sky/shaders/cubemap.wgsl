@@ -0,0 +1,97 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+
+#import bevy_pbr::mesh_functions
+
+#import bevy_atmosphere::types
+#import bevy_atmosphere::math
+
+struct Vertex {
+    @location(0)
+        position: vec3<f32>,
+    @location(1)
+        normal: vec3<f32>,
+    @location(2)
+        uv: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position)
+        clip_position: vec4<f32>,
+    @location(0)
+        ray: vec3<f32>,
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    var out: VertexOutput;
+    out.clip_position = mesh_position_world_to_clip(world_position);
+    // the sky lies on the far plane of the reversed depth, behind everything any camera drew,
+    // and is looked at from the camera drawing it, even through the sky box of another camera
+    out.clip_position.z = 0.0;
+    out.ray = world_position.xyz - view.world_position;
+    return out;
+}
+
+@group(1) @binding(0)
+var cubemap: texture_2d<f32>;
+@group(1) @binding(1)
+var cubemap_sampler: sampler;
+@group(1) @binding(2)
+var<uniform> atmosphere: Atmosphere;
+
+struct FragmentOutput {
+    @location(0)
+        color: vec4<f32>,
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> FragmentOutput {
+    var out: FragmentOutput;
+
+    let resolution = f32(textureDimensions(cubemap).y);
+    let uv = sky_face_uv(normalize(in.ray), resolution);
+    let scattering = textureSample(cubemap, cubemap_sampler, uv).rgb;
+
+    // the moon, the stars and the clouds are too small or move too fast for the cubemap
+    let moon = render_moon(
+        in.ray,
+        atmosphere.ray_origin,
+        atmosphere.planet_radius,
+        atmosphere.moon_position,
+        atmosphere.moon_phase,
+        atmosphere.moon_radius,
+    );
+    let moon_color = vec3<f32>(0.9f, 0.92f, 1f) * atmosphere.moon_intensity * moon;
+
+    let stars = render_stars(
+        in.ray,
+        atmosphere.ray_origin,
+        atmosphere.planet_radius,
+        atmosphere.sun_position,
+        atmosphere.star_density,
+        atmosphere.star_twinkle,
+        atmosphere.star_rotation,
+        atmosphere.time,
+    );
+
+    let clouds = render_clouds(
+        in.ray,
+        atmosphere.ray_origin,
+        atmosphere.planet_radius,
+        atmosphere.sun_position,
+        atmosphere.cloud_height,
+        atmosphere.cloud_coverage,
+        atmosphere.cloud_scale,
+        atmosphere.wind,
+        atmosphere.time,
+    );
+
+    // the lightning lights up the clouds more than the clear sky between them
+    let flash = vec3<f32>(0.75, 0.8, 1f) * atmosphere.lightning;
+    let sky = scattering + moon_color + vec3<f32>(stars) + flash * 0.3;
+    out.color = vec4<f32>(mix(sky, clouds.rgb + flash, clouds.a), 1f);
+
+    return out;
+}
@@ -27,6 +27,8 @@ fn vertex(vertex: Vertex) -> VertexOutput {
     let position = vertex.position;
     var out: VertexOutput;
     out.clip_position = mesh_position_local_to_clip(mesh.model, vec4<f32>(position, 1.0));
+    // the sky lies on the far plane of the reversed depth
+    out.clip_position.z = 0.0;
     out.ray = position;
     return out;
 }
@@ -56,7 +58,8 @@ fn fragment(in: VertexOutput) -> FragmentOutput {
         atmosphere.mie_scale_height,
         atmosphere.mie_direction,
     );
-    
+
+    // only the scattering is baked, the sky boxes draw the rest on top of it
     out.color = vec4<f32>(1f - exp(-1f * render), 1f);
 
     return out;
@@ -3,6 +3,9 @@
 let PI: f32 = 3.141592653589793;
 let ISTEPS: u32 = 16u;
 let JSTEPS: u32 = 8u;
+let STAR_CELLS: f32 = 150.0;
+let CLOUD_OCTAVES: u32 = 5u;
+let CLOUD_LIGHT_STEPS: u32 = 4u;
 
 fn rsi(rd: vec3<f32>, r0: vec3<f32>, sr: f32) -> vec2<f32> {
     // ray-sphere intersection that assumes
@@ -109,3 +112,160 @@ fn render_atmosphere(r: vec3<f32>, r0: vec3<f32>, p_sun: vec3<f32>, i_sun: f32,
     // Calculate and return the final color.
     return i_sun * (p_rlh * k_rlh * total_rlh + p_mie * k_mie * total_mie);
 }
+
+
+fn render_moon(r: vec3<f32>, r0: vec3<f32>, r_planet: f32, p_moon: vec3<f32>, phase: f32, radius: f32) -> f32 {
+    // Normalize the ray direction and moon position.
+    let r = normalize(r);
+    let p_moon = normalize(p_moon);
+
+    // The planet hides the moon below the horizon.
+    let planet = rsi(r, r0, r_planet);
+    if (planet.x <= planet.y && planet.y > 0.0) { return 0f; }
+
+    // Find where the ray crosses the moon disc, in units of its radius.
+    let right = normalize(cross(p_moon, vec3<f32>(0f, 1f, 0f) + vec3<f32>(0f, 0f, 1e-4)));
+    let up = cross(right, p_moon);
+    let offset = r - p_moon * dot(r, p_moon);
+    let x = dot(offset, right) / sin(radius);
+    let y = dot(offset, up) / sin(radius);
+    let rr = x * x + y * y;
+    if (dot(r, p_moon) <= 0.0 || rr > 1.0) { return 0f; }
+
+    // Light the moon as a sphere, the sun is behind it at new moon and behind the viewer at full moon.
+    let normal = vec3<f32>(x, y, sqrt(1.0 - rr));
+    let angle = PI + 2.0 * PI * phase;
+    let light = vec3<f32>(sin(angle), 0f, -cos(angle));
+
+    // A faint earthshine keeps the dark part of the disc visible.
+    return max(dot(normal, light), 0f) + 0.02;
+}
+
+fn hash3(p: vec3<f32>) -> f32 {
+    return fract(sin(dot(p, vec3<f32>(127.1, 311.7, 74.7))) * 43758.5453);
+}
+
+fn render_stars(r: vec3<f32>, r0: vec3<f32>, r_planet: f32, p_sun: vec3<f32>, density: f32, twinkle: f32, rotation: f32, time: f32) -> f32 {
+    // Normalize the ray direction and sun position.
+    let r = normalize(r);
+    let p_sun = normalize(p_sun);
+
+    // The stars fade in while the sun goes below the horizon.
+    let night = 1.0 - smoothstep(-0.15, 0.05, p_sun.y);
+    if (night <= 0.0) { return 0f; }
+
+    // The planet hides the stars below the horizon.
+    let planet = rsi(r, r0, r_planet);
+    if (planet.x <= planet.y && planet.y > 0.0) { return 0f; }
+
+    // Turn the ray back around the celestial pole, so the stars stay fixed to the turning sky.
+    let c = cos(-rotation);
+    let s = sin(-rotation);
+    let d = vec3<f32>(r.x, c * r.y - s * r.z, s * r.y + c * r.z);
+
+    // Each cell of a grid around the viewer may hold one star, somewhere inside it.
+    let p = d * STAR_CELLS;
+    let cell = floor(p);
+    if (hash3(cell) > density) { return 0f; }
+    let star = cell + vec3<f32>(hash3(cell + 1.0), hash3(cell + 2.0), hash3(cell + 3.0));
+    let glow = 1.0 - smoothstep(0.0, 0.12, length(p - star));
+
+    // Every star flickers at its own pace.
+    let seed = hash3(cell + 4.0);
+    let flicker = 1.0 - twinkle * (0.5 + 0.5 * sin(time * (2.0 + 4.0 * seed) + seed * 2.0 * PI));
+
+    return glow * flicker * (0.4 + 0.6 * seed) * night;
+}
+
+fn hash2(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2<f32>(127.1, 311.7))) * 43758.5453);
+}
+
+fn value_noise(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let a = hash2(i);
+    let b = hash2(i + vec2<f32>(1f, 0f));
+    let c = hash2(i + vec2<f32>(0f, 1f));
+    let d = hash2(i + vec2<f32>(1f, 1f));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+fn fbm(p: vec2<f32>) -> f32 {
+    var value = 0f;
+    var amplitude = 0.5;
+    var q = p;
+    for (var i = 0u; i < CLOUD_OCTAVES; i++) {
+        value += amplitude * value_noise(q);
+        q = q * 2.03 + vec2<f32>(17.1, 9.2);
+        amplitude *= 0.5;
+    }
+    return value;
+}
+
+fn cloud_density(uv: vec2<f32>, coverage: f32) -> f32 {
+    let threshold = 1.0 - coverage;
+    return smoothstep(threshold - 0.1, threshold + 0.15, fbm(uv));
+}
+
+fn render_clouds(r: vec3<f32>, r0: vec3<f32>, r_planet: f32, p_sun: vec3<f32>, height: f32, coverage: f32, scale: f32, wind: vec2<f32>, time: f32) -> vec4<f32> {
+    // Normalize the ray direction and sun position.
+    let r = normalize(r);
+    let p_sun = normalize(p_sun);
+
+    // Find where the ray leaves the cloud layer, seen from below it.
+    if (r.y <= 0.0 || coverage <= 0.0) { return vec4<f32>(0f); }
+    let t = rsi(r, r0, r_planet + height).y;
+    if (t <= 0.0) { return vec4<f32>(0f); }
+    let pos = r0 + r * t;
+
+    // The clouds drift with the wind.
+    let uv = (pos.xz - wind * time) / scale;
+    let density = cloud_density(uv, coverage);
+    if (density <= 0.0) { return vec4<f32>(0f); }
+
+    // March towards the sun through the layer, thick clouds shade their own far side.
+    var shadow = 0f;
+    for (var i = 1u; i <= CLOUD_LIGHT_STEPS; i++) {
+        shadow += cloud_density(uv + p_sun.xz * f32(i) * 0.04, coverage);
+    }
+    let light = exp(-shadow * 0.6);
+
+    // The clouds darken as the sun sets, and take a warm tint near the horizon.
+    let daylight = clamp(p_sun.y * 3.0 + 0.3, 0.0, 1.0);
+    let sunset = vec3<f32>(1.0, 0.75, 0.55);
+    let lit = mix(sunset, vec3<f32>(1f), clamp(p_sun.y * 4.0, 0.0, 1.0));
+    let color = mix(vec3<f32>(0.3, 0.32, 0.38), lit, light) * mix(0.04, 1.0, daylight);
+
+    // Far clouds fade into the haze of the horizon.
+    let fade = smoothstep(0.0, 0.12, r.y);
+    return vec4<f32>(color, density * fade);
+}
+
+fn sky_face_uv(d: vec3<f32>, resolution: f32) -> vec2<f32> {
+    // Pick the face the direction points through, in the order +x, -x, +y, -y, +z, -z,
+    // with the forward and up directions of the camera that baked it.
+    let a = abs(d);
+    var face = 0f;
+    var f = vec3<f32>(0f, 0f, 0f);
+    var u = vec3<f32>(0f, 1f, 0f);
+    if (a.x >= a.y && a.x >= a.z) {
+        face = select(1f, 0f, d.x > 0.0);
+        f = vec3<f32>(sign(d.x), 0f, 0f);
+    } else if (a.y >= a.z) {
+        face = select(3f, 2f, d.y > 0.0);
+        f = vec3<f32>(0f, sign(d.y), 0f);
+        u = vec3<f32>(0f, 0f, sign(d.y));
+    } else {
+        face = select(5f, 4f, d.z > 0.0);
+        f = vec3<f32>(0f, 0f, sign(d.z));
+    }
+
+    // Project onto the face like its camera did, keeping half a texel away from the neighbouring faces.
+    let r = cross(f, u);
+    let limit = 1.0 - 1.0 / resolution;
+    let x = clamp(dot(d, r) / dot(d, f), -limit, limit);
+    let y = clamp(dot(d, u) / dot(d, f), -limit, limit);
+    return vec2<f32>((face + (x + 1.0) * 0.5) / 6.0, (1.0 - y) * 0.5);
+}
@@ -11,4 +11,17 @@ struct Atmosphere {
     mie_coefficient: f32,
     mie_scale_height: f32,
     mie_direction: f32,
+    moon_position: vec3<f32>,
+    moon_intensity: f32,
+    moon_phase: f32,
+    moon_radius: f32,
+    star_density: f32,
+    star_twinkle: f32,
+    star_rotation: f32,
+    time: f32,
+    cloud_height: f32,
+    cloud_coverage: f32,
+    cloud_scale: f32,
+    wind: vec2<f32>,
+    lightning: f32,
 }
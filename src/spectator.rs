@@ -0,0 +1,76 @@
+use bevy::{
+    prelude::*,
+    core_pipeline::clear_color::ClearColorConfig,
+    render::camera::Viewport
+};
+
+use crate::{
+    player::Player,
+    sky::AtmosphereCamera
+};
+
+/// Where the spectator looks at the player from
+const SPECTATOR_OFFSET: Vec3 = Vec3::new(12.0, 10.0, 12.0);
+/// Part of the window width and height the spectator view covers, in its top right corner
+const SPECTATOR_VIEW_SIZE: f32 = 0.3;
+
+/// A second camera drawn in a corner of the window, it has a sky of its own
+///
+/// F10 shows and hides it, it is there to look at the world, and the sky, from two places at once
+#[derive(Component)]
+pub struct Spectator;
+
+pub fn toggle_spectator(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    spectators: Query<Entity, With<Spectator>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F10) {
+        return;
+    }
+    if let Ok(spectator) = spectators.get_single() {
+        commands.entity(spectator).despawn_recursive();
+        return;
+    }
+    commands.spawn_bundle(Camera3dBundle {
+        camera: Camera {
+            // drawn after the player camera, over its corner
+            priority: 1,
+            ..default()
+        },
+        camera_3d: Camera3d {
+            // the clear would wipe the whole window, the sky covers the view anyway
+            clear_color: ClearColorConfig::None,
+            ..default()
+        },
+        ..default()
+    })
+    .insert(UiCameraConfig { show_ui: false })
+    .insert(AtmosphereCamera)
+    .insert(Spectator);
+}
+
+/// Keeps the spectator looking at the player and its view in the corner of the window
+pub fn follow_spectator(
+    windows: Res<Windows>,
+    player: Query<&Transform, (With<Player>, Without<Spectator>)>,
+    mut spectators: Query<(&mut Transform, &mut Camera), With<Spectator>>,
+) {
+    let (mut transform, mut camera) = match spectators.get_single_mut() {
+        Ok(spectator) => spectator,
+        Err(_) => return,
+    };
+    if let Ok(player) = player.get_single() {
+        *transform = Transform::from_translation(player.translation + SPECTATOR_OFFSET)
+            .looking_at(player.translation, Vec3::Y);
+    }
+    if let Some(window) = windows.get_primary() {
+        let window_size = UVec2::new(window.physical_width(), window.physical_height());
+        let size = (window_size.as_vec2() * SPECTATOR_VIEW_SIZE).as_uvec2().max(UVec2::ONE);
+        camera.viewport = Some(Viewport {
+            physical_position: UVec2::new(window_size.x - size.x, 0),
+            physical_size: size,
+            ..default()
+        });
+    }
+}
\ No newline at end of file
@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    model::BlockShape,
+    world::Face
+};
+
+/// A state property a block can declare in assets/blocks.json
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Property {
+    /// The horizontal face the block points to
+    Facing,
+    /// The axis the block lies along
+    Axis,
+    /// Whether the block sits in the lower or the upper half of its cell
+    Half,
+    Open,
+    /// Whether the cell is filled with water around the block
+    Waterlogged,
+}
+
+impl Property {
+    /// The given state with each value of the property, the default value first
+    fn variants(self, state: StateProperties) -> Vec<StateProperties> {
+        match self {
+            Property::Facing => [Face::North, Face::East, Face::South, Face::West]
+                .map(|facing| StateProperties { facing, ..state })
+                .to_vec(),
+            Property::Axis => [Axis::Y, Axis::X, Axis::Z]
+                .map(|axis| StateProperties { axis, ..state })
+                .to_vec(),
+            Property::Half => [Half::Bottom, Half::Top]
+                .map(|half| StateProperties { half, ..state })
+                .to_vec(),
+            Property::Open => [false, true]
+                .map(|open| StateProperties { open, ..state })
+                .to_vec(),
+            Property::Waterlogged => [false, true]
+                .map(|waterlogged| StateProperties { waterlogged, ..state })
+                .to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Axis {
+    X,
+    #[default]
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub fn of_face(face: Face) -> Axis {
+        match face {
+            Face::East | Face::West => Axis::X,
+            Face::Up | Face::Down => Axis::Y,
+            Face::South | Face::North => Axis::Z,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Half {
+    #[default]
+    Bottom,
+    Top,
+}
+
+/// The value of every property, the ones a block does not declare keep their default
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct StateProperties {
+    pub facing: Face,
+    pub axis: Axis,
+    pub half: Half,
+    pub open: bool,
+    pub waterlogged: bool,
+}
+
+impl StateProperties {
+    /// Every combination of values of the given properties, the default state first
+    pub fn combinations(properties: &[Property]) -> Vec<StateProperties> {
+        let mut states = vec![StateProperties::default()];
+        for property in properties {
+            states = states.into_iter().flat_map(|state| property.variants(state)).collect();
+        }
+        states
+    }
+
+    /// Resets the values of the properties which are not in the given list
+    pub fn restrict(self, properties: &[Property]) -> Self {
+        let default = Self::default();
+        let has = |property| properties.contains(&property);
+        Self {
+            facing: if has(Property::Facing) { self.facing } else { default.facing },
+            axis: if has(Property::Axis) { self.axis } else { default.axis },
+            half: if has(Property::Half) { self.half } else { default.half },
+            open: if has(Property::Open) { self.open } else { default.open },
+            waterlogged: if has(Property::Waterlogged) { self.waterlogged } else { default.waterlogged },
+        }
+    }
+
+    /// The face of the upright block whose texture is drawn on face, blocks lying along X or Z show their top at the ends
+    pub fn texture_face(&self, face: Face) -> Face {
+        match (self.axis, face) {
+            (Axis::Y, _) => face,
+            (Axis::X, Face::East) | (Axis::Z, Face::South) => Face::Up,
+            (Axis::X, Face::West) | (Axis::Z, Face::North) => Face::Down,
+            (_, Face::Up | Face::Down) => Face::South,
+            _ => face,
+        }
+    }
+}
+
+/// The horizontal face closest to a direction
+pub fn horizontal_facing(direction: Vec3) -> Face {
+    if direction.x.abs() > direction.z.abs() {
+        if direction.x > 0.0 { Face::East } else { Face::West }
+    } else if direction.z > 0.0 {
+        Face::South
+    } else {
+        Face::North
+    }
+}
+
+/// Properties of a block placed against the clicked face of another block
+///
+/// height is how far above the center of the new cell the clicked point is
+pub fn placement_properties(shape: BlockShape, face: Face, look: Vec3, height: f32, in_water: bool) -> StateProperties {
+    let facing = match shape {
+        // trapdoors hang on the block they were placed against
+        BlockShape::Trapdoor if Axis::of_face(face) != Axis::Y => face.opposite(),
+        // stairs face away from the player, so that they can be walked up
+        _ => horizontal_facing(look),
+    };
+    StateProperties {
+        facing,
+        axis: Axis::of_face(face),
+        half: if height > 0.0 { Half::Top } else { Half::Bottom },
+        open: false,
+        waterlogged: in_water,
+    }
+}
\ No newline at end of file
@@ -0,0 +1,112 @@
+use bevy::{
+    asset::load_internal_asset,
+    pbr::{
+        MaterialPipeline,
+        MaterialPipelineKey
+    },
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_resource::{
+            AsBindGroup,
+            RenderPipelineDescriptor,
+            ShaderRef,
+            SpecializedMeshPipelineError
+        }
+    }
+};
+
+use crate::{
+    atlas::BlockAtlas,
+    fog::Fog
+};
+
+pub const TERRAIN_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 11764012396551834219);
+
+/// The material of the meshes drawn with the block atlas, lit by the vertex colours and tinted by the sky,
+/// and faded into the colour of the sky with the distance
+#[derive(AsBindGroup, TypeUuid, Debug, Clone)]
+#[uuid = "3c1f3e0e-4f5b-4a3a-9f2e-7d6c1b8a2e51"]
+pub struct TerrainMaterial {
+    /// The baked sky, the fog and the ambient tint take their colour from it
+    #[texture(0)]
+    #[sampler(1)]
+    pub sky: Handle<Image>,
+    #[uniform(2)]
+    pub fog: Fog,
+    #[texture(3)]
+    #[sampler(4)]
+    pub texture: Handle<Image>,
+    /// Fragments whose alpha is below this are dropped, 0 keeps every fragment
+    #[uniform(5)]
+    pub alpha_cutoff: f32,
+    pub alpha_mode: AlphaMode,
+}
+
+impl Material for TerrainMaterial {
+    fn vertex_shader() -> ShaderRef {
+        TERRAIN_SHADER_HANDLE.typed().into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        TERRAIN_SHADER_HANDLE.typed().into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(3),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        Ok(())
+    }
+}
+
+/// Draws the blocks with the TerrainMaterial, it needs the shaders and the sky cubemap of the AtmospherePlugin
+pub struct TerrainMaterialPlugin;
+
+impl Plugin for TerrainMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            TERRAIN_SHADER_HANDLE,
+            "shaders/terrain.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.add_plugin(MaterialPlugin::<TerrainMaterial>::default());
+    }
+}
+
+/// Copies the fog into the materials of the atlas when it changes
+pub fn update_terrain_materials(
+    atlas: Option<Res<BlockAtlas>>,
+    fog: Res<Fog>,
+    mut materials: ResMut<Assets<TerrainMaterial>>,
+) {
+    let atlas = match atlas {
+        Some(atlas) => atlas,
+        None => return,
+    };
+    if !fog.is_changed() && !atlas.is_added() {
+        return;
+    }
+    for handle in atlas.materials() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.fog = *fog;
+        }
+    }
+}
\ No newline at end of file
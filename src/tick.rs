@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+
+use crate::{
+    registry::BlockRegistry,
+    utils::Rng,
+    world::{
+        BlockChanged,
+        VoxelWorld,
+        CHUNK_SIZE,
+        NEIGHBOURS,
+        SECTION_COUNT
+    }
+};
+
+use std::collections::BTreeMap;
+
+/// The world is simulated at 20 ticks per second
+pub const TICKS_PER_SECOND: f64 = 20.0;
+pub const TICK_STEP: f64 = 1.0 / TICKS_PER_SECOND;
+
+/// Keeps the block updates which are waiting for their tick
+pub struct TickScheduler {
+    pub tick: u64,
+    /// Number of blocks picked in each chunk section every tick
+    pub random_tick_speed: u32,
+    scheduled: BTreeMap<u64, Vec<IVec3>>,
+    rng: Rng,
+}
+
+impl TickScheduler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            tick: 0,
+            random_tick_speed: 3,
+            scheduled: BTreeMap::new(),
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Asks for the block at pos to be updated in delay ticks
+    pub fn schedule(&mut self, pos: IVec3, delay: u32) {
+        let due = self.tick + delay.max(1) as u64;
+        let positions = self.scheduled.entry(due).or_default();
+        if !positions.contains(&pos) {
+            positions.push(pos);
+        }
+    }
+
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    fn take_due(&mut self) -> Vec<IVec3> {
+        let later = self.scheduled.split_off(&(self.tick + 1));
+        let due = std::mem::replace(&mut self.scheduled, later);
+        due.into_values().flatten().collect()
+    }
+
+    /// The loaded chunks in the order they get their random ticks, which does not depend on the
+    /// order they were loaded in so that the rng stays deterministic
+    fn random_tick_chunks(world: &VoxelWorld) -> Vec<IVec2> {
+        let mut chunks: Vec<IVec2> = world.chunk_coords().collect();
+        chunks.sort_by_key(|coord| (coord.x, coord.y));
+        chunks
+    }
+
+    /// Picks the next block of a chunk section to get a random tick
+    fn random_tick_pos(&mut self, coord: IVec2, section: i32) -> IVec3 {
+        let rng = self.rng();
+        IVec3::new(
+            coord.x * CHUNK_SIZE + rng.next_below(CHUNK_SIZE as u32) as i32,
+            section * CHUNK_SIZE + rng.next_below(CHUNK_SIZE as u32) as i32,
+            coord.y * CHUNK_SIZE + rng.next_below(CHUNK_SIZE as u32) as i32,
+        )
+    }
+}
+
+impl Default for TickScheduler {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// Everything a block behaviour may read or change during its update
+pub struct TickContext<'a, 'w, 's> {
+    pub commands: &'a mut Commands<'w, 's>,
+    pub world: &'a mut VoxelWorld,
+    pub registry: &'a BlockRegistry,
+    pub scheduler: &'a mut TickScheduler,
+}
+
+/// Advances the simulation by one tick, run with a fixed timestep of TICK_STEP
+pub fn world_tick(
+    mut commands: Commands,
+    mut world: ResMut<VoxelWorld>,
+    registry: Res<BlockRegistry>,
+    mut scheduler: ResMut<TickScheduler>,
+) {
+    scheduler.tick += 1;
+    let mut context = TickContext {
+        commands: &mut commands,
+        world: &mut *world,
+        registry: &*registry,
+        scheduler: &mut *scheduler,
+    };
+
+    for pos in context.scheduler.take_due() {
+        let id = context.world.get_block(pos);
+        if let Some(handler) = context.registry.behaviour(id).on_scheduled_tick {
+            handler(&mut context, pos);
+        }
+    }
+
+    for coord in TickScheduler::random_tick_chunks(context.world) {
+        for section in 0..SECTION_COUNT {
+            for _ in 0..context.scheduler.random_tick_speed {
+                let pos = context.scheduler.random_tick_pos(coord, section);
+                let id = context.world.get_block(pos);
+                if let Some(handler) = context.registry.behaviour(id).on_random_tick {
+                    handler(&mut context, pos);
+                }
+            }
+        }
+    }
+}
+
+/// Schedules an update for the blocks next to every changed block
+pub fn schedule_neighbour_ticks(
+    mut events: EventReader<BlockChanged>,
+    world: Res<VoxelWorld>,
+    registry: Res<BlockRegistry>,
+    mut scheduler: ResMut<TickScheduler>,
+) {
+    for change in events.iter() {
+        for pos in std::iter::once(change.pos).chain(NEIGHBOURS.iter().map(|offset| change.pos + *offset)) {
+            if let Some(delay) = registry.behaviour(world.get_block(pos)).neighbour_tick_delay {
+                scheduler.schedule(pos, delay);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Chunk, CHUNK_HEIGHT};
+    use ndarray::Array3;
+
+    fn world(coords: &[IVec2]) -> VoxelWorld {
+        let mut world = VoxelWorld::default();
+        for coord in coords {
+            let size = CHUNK_SIZE as usize;
+            world.insert_chunk(*coord, Chunk::from(Array3::zeros((size, CHUNK_HEIGHT as usize, size))));
+        }
+        world
+    }
+
+    // the blocks picked for one tick of random updates
+    fn random_ticks(scheduler: &mut TickScheduler, world: &VoxelWorld) -> Vec<IVec3> {
+        let mut picked = Vec::new();
+        for coord in TickScheduler::random_tick_chunks(world) {
+            for section in 0..SECTION_COUNT {
+                for _ in 0..scheduler.random_tick_speed {
+                    picked.push(scheduler.random_tick_pos(coord, section));
+                }
+            }
+        }
+        picked
+    }
+
+    #[test]
+    fn same_seed_picks_the_same_random_ticks() {
+        let coords = [IVec2::new(0, 0), IVec2::new(-1, 2), IVec2::new(3, -4), IVec2::new(1, 1)];
+        let mut reversed = coords;
+        reversed.reverse();
+        let (first_world, second_world) = (world(&coords), world(&reversed));
+        let mut first = TickScheduler::new(42);
+        let mut second = TickScheduler::new(42);
+        for _ in 0..3 {
+            let picked = random_ticks(&mut first, &first_world);
+            assert_eq!(picked, random_ticks(&mut second, &second_world));
+            for pos in picked {
+                let coord = VoxelWorld::chunk_coord(pos);
+                assert!(coords.contains(&coord), "{:?} is outside of the chunks", pos);
+            }
+        }
+        assert_eq!(first.rng().next_u64(), second.rng().next_u64());
+        assert_ne!(random_ticks(&mut TickScheduler::new(7), &first_world), random_ticks(&mut first, &first_world));
+    }
+
+    #[test]
+    fn scheduled_ticks_come_due_in_order() {
+        let schedule = |scheduler: &mut TickScheduler| {
+            scheduler.schedule(IVec3::new(1, 0, 0), 2);
+            scheduler.schedule(IVec3::new(2, 0, 0), 1);
+            scheduler.schedule(IVec3::new(3, 0, 0), 2);
+            // the same block is only updated once per tick
+            scheduler.schedule(IVec3::new(1, 0, 0), 2);
+            // a block can not wait for less than one tick
+            scheduler.schedule(IVec3::new(4, 0, 0), 0);
+        };
+        let mut first = TickScheduler::new(42);
+        let mut second = TickScheduler::new(42);
+        schedule(&mut first);
+        schedule(&mut second);
+        let mut due = Vec::new();
+        for _ in 0..3 {
+            first.tick += 1;
+            second.tick += 1;
+            let taken = first.take_due();
+            assert_eq!(taken, second.take_due());
+            due.push(taken);
+        }
+        assert_eq!(due, vec![
+            vec![IVec3::new(2, 0, 0), IVec3::new(4, 0, 0)],
+            vec![IVec3::new(1, 0, 0), IVec3::new(3, 0, 0)],
+            vec![],
+        ]);
+    }
+}
\ No newline at end of file
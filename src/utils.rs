@@ -1,5 +1,36 @@
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
 
 pub fn to_radians(x: f32) -> f32 { x * PI / 180.0 }
 
-pub fn decimal_round(x: f32) -> f32 { (x * 100.0).round() / 100.0 }
\ No newline at end of file
+/// Deterministic pseudo random number generator (SplitMix64)
+///
+/// The same seed always produces the same sequence, on every platform
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform integer in 0..bound
+    pub fn next_below(&mut self, bound: u32) -> u32 {
+        ((self.next_u64() >> 32) * bound as u64 >> 32) as u32
+    }
+
+    /// Uniform float in 0.0..1.0
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
\ No newline at end of file
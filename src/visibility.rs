@@ -0,0 +1,208 @@
+use bevy::{
+    prelude::*,
+    render::primitives::Frustum
+};
+
+use crate::{
+    chunk_mesh::ChunkMeshes,
+    loader::ChunkLoader,
+    player::PlayerCamera,
+    world::{
+        Face,
+        VoxelWorld,
+        CHUNK_SIZE,
+        SECTION_COUNT
+    }
+};
+
+use std::collections::{HashSet, VecDeque};
+
+/// Which faces of a section can see each other through the open blocks inside it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Connectivity(u64);
+
+impl Connectivity {
+    /// Every face sees every other one, like in an empty or an unknown section
+    pub const ALL: Connectivity = Connectivity((1 << 36) - 1);
+
+    fn bit(a: Face, b: Face) -> u64 {
+        1 << (a as usize * 6 + b as usize)
+    }
+
+    pub fn connects(self, a: Face, b: Face) -> bool {
+        self.0 & Self::bit(a, b) != 0
+    }
+
+    /// Flood fills the open blocks of a section, the faces reached by one fill all see each other
+    ///
+    /// is_open is given positions inside the section, from 0 to CHUNK_SIZE - 1 on every axis
+    pub fn compute(is_open: impl Fn(IVec3) -> bool) -> Self {
+        let size = CHUNK_SIZE as usize;
+        let index = |pos: IVec3| (pos.y as usize * size + pos.z as usize) * size + pos.x as usize;
+        let mut visited = vec![false; size * size * size];
+        let mut connectivity = Connectivity(0);
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let start = IVec3::new(x, y, z);
+                    if visited[index(start)] || !is_open(start) {
+                        continue;
+                    }
+                    visited[index(start)] = true;
+                    let mut faces = Vec::new();
+                    let mut stack = vec![start];
+                    while let Some(pos) = stack.pop() {
+                        for face in Face::ALL {
+                            let neighbour = pos + face.normal();
+                            if neighbour.min_element() < 0 || neighbour.max_element() >= CHUNK_SIZE {
+                                if !faces.contains(&face) {
+                                    faces.push(face);
+                                }
+                                continue;
+                            }
+                            if !visited[index(neighbour)] && is_open(neighbour) {
+                                visited[index(neighbour)] = true;
+                                stack.push(neighbour);
+                            }
+                        }
+                    }
+                    for a in &faces {
+                        for b in &faces {
+                            connectivity.0 |= Self::bit(*a, *b);
+                        }
+                    }
+                }
+            }
+        }
+        connectivity
+    }
+}
+
+/// The sections which can be seen from the section of the camera
+///
+/// The walk only goes through faces its section connects to the face it entered through,
+/// and never back towards the camera, so sections behind sealed walls are left out
+pub fn visible_sections(
+    start: IVec3,
+    connectivity: impl Fn(IVec3) -> Connectivity,
+    in_view: impl Fn(IVec3) -> bool,
+) -> HashSet<IVec3> {
+    let mut visible = HashSet::from([start]);
+    // the section, the face it was entered through and the directions taken to get there
+    let mut queue = VecDeque::from([(start, None, 0u8)]);
+    while let Some((section, entered, directions)) = queue.pop_front() {
+        let section_connectivity = connectivity(section);
+        for face in Face::ALL {
+            if directions & (1 << face.opposite() as usize) != 0 {
+                continue;
+            }
+            if let Some(entered) = entered {
+                if !section_connectivity.connects(entered, face) {
+                    continue;
+                }
+            }
+            let next = section + face.normal();
+            if visible.contains(&next) || !in_view(next) {
+                continue;
+            }
+            visible.insert(next);
+            queue.push_back((next, Some(face.opposite()), directions | (1 << face as usize)));
+        }
+    }
+    visible
+}
+
+// whether the box of a section is at least partly on the inner side of every plane of the frustum
+fn in_frustum(frustum: &Frustum, section: IVec3) -> bool {
+    let center = (section * CHUNK_SIZE).as_vec3() + Vec3::splat((CHUNK_SIZE - 1) as f32 / 2.0);
+    let half_extents = Vec3::splat(CHUNK_SIZE as f32 / 2.0);
+    frustum.planes.iter().all(|plane| {
+        let normal_d = plane.normal_d();
+        let normal = normal_d.truncate();
+        normal.dot(center) + normal_d.w + normal.abs().dot(half_extents) >= 0.0
+    })
+}
+
+/// Hides the section meshes outside of the view frustum or behind the walls of caves
+pub fn cull_sections(
+    camera: Query<(&GlobalTransform, &Frustum), With<PlayerCamera>>,
+    chunk_meshes: Res<ChunkMeshes>,
+    loader: Res<ChunkLoader>,
+    mut visibilities: Query<&mut Visibility>,
+) {
+    let (transform, frustum) = match camera.get_single() {
+        Ok(camera) => camera,
+        _ => return,
+    };
+    let mut start = VoxelWorld::section_coord(transform.translation().floor().as_ivec3());
+    start.y = start.y.clamp(0, SECTION_COUNT - 1);
+    let visible = visible_sections(
+        start,
+        |section| chunk_meshes.connectivity(section),
+        |section| {
+            section.y >= 0
+                && section.y < SECTION_COUNT
+                && (section.x - start.x).abs().max((section.z - start.z).abs()) <= loader.view_distance
+                && in_frustum(frustum, section)
+        },
+    );
+    for (section, entities) in chunk_meshes.section_entities() {
+        let is_visible = visible.contains(&section);
+        for entity in entities {
+            if let Ok(mut visibility) = visibilities.get_mut(*entity) {
+                if visibility.is_visible != is_visible {
+                    visibility.is_visible = is_visible;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solid_section_connects_no_faces() {
+        let connectivity = Connectivity::compute(|_| false);
+        for a in Face::ALL {
+            for b in Face::ALL {
+                assert!(!connectivity.connects(a, b), "{:?} sees {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_section_connects_all_faces() {
+        assert_eq!(Connectivity::compute(|_| true), Connectivity::ALL);
+    }
+
+    #[test]
+    fn tunnel_connects_its_two_ends() {
+        let middle = CHUNK_SIZE / 2;
+        let connectivity = Connectivity::compute(|pos| pos.y == middle && pos.z == middle);
+        for a in Face::ALL {
+            for b in Face::ALL {
+                let ends = [Face::East, Face::West];
+                let expected = ends.contains(&a) && ends.contains(&b);
+                assert_eq!(connectivity.connects(a, b), expected, "{:?} and {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn walk_stops_at_a_sealed_wall() {
+        let in_view = |section: IVec3| section.y == 0 && section.z == 0 && (0..4).contains(&section.x);
+        let open = visible_sections(IVec3::ZERO, |_| Connectivity::ALL, in_view);
+        assert_eq!(open.len(), 4);
+
+        let wall = Connectivity::compute(|_| false);
+        let visible = visible_sections(
+            IVec3::ZERO,
+            |section| if section.x == 1 { wall } else { Connectivity::ALL },
+            in_view,
+        );
+        // the wall itself is seen, what lies behind it is not
+        assert_eq!(visible, HashSet::from([IVec3::ZERO, IVec3::X]));
+    }
+}
\ No newline at end of file
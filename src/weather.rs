@@ -0,0 +1,254 @@
+use bevy::prelude::*;
+use noise::Perlin;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    generator::{
+        temperature,
+        SNOW_TEMPERATURE
+    },
+    player::Player,
+    registry::BlockRegistry,
+    sky::{
+        material::Atmosphere,
+        preset::AtmosphereTransition
+    },
+    tick::TICKS_PER_SECOND,
+    utils::Rng,
+    world::VoxelWorld,
+    WORLD_SEED
+};
+
+/// Ticks it takes the rain and the storm to fully set in or clear up
+const WEATHER_FADE_TICKS: f32 = 200.0;
+/// Number of drops falling around the player in the heaviest rain
+const MAX_DROPS: usize = 800;
+/// Drops fall within this many blocks of the player
+const DROP_RADIUS: f32 = 20.0;
+/// Added to the world seed so the weather does not draw the same numbers as the random ticks
+const WEATHER_SEED: u64 = 0x5745_4154_4845_5200;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WeatherState {
+    #[default]
+    Clear,
+    Rain,
+    Thunder,
+}
+
+impl WeatherState {
+    /// The state which follows this one and how many ticks it lasts
+    fn next(self, rng: &mut Rng) -> (WeatherState, u64) {
+        let roll = rng.next_f32();
+        let next = match self {
+            WeatherState::Clear if roll < 0.75 => WeatherState::Rain,
+            WeatherState::Clear => WeatherState::Thunder,
+            WeatherState::Rain if roll < 0.6 => WeatherState::Clear,
+            WeatherState::Rain => WeatherState::Thunder,
+            WeatherState::Thunder if roll < 0.5 => WeatherState::Rain,
+            WeatherState::Thunder => WeatherState::Clear,
+        };
+        (next, next.duration(rng))
+    }
+
+    fn duration(self, rng: &mut Rng) -> u64 {
+        let (min, spread) = match self {
+            WeatherState::Clear => (6000, 12000),
+            WeatherState::Rain => (3000, 6000),
+            WeatherState::Thunder => (2000, 4000),
+        };
+        min + rng.next_below(spread) as u64
+    }
+}
+
+/// The weather of the world, it changes on its own after a random number of ticks
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Weather {
+    pub state: WeatherState,
+    /// Ticks until the weather changes
+    pub remaining: u64,
+    /// How heavy the rain is, from 0 to 1, it eases towards the state
+    pub rain: f32,
+    /// How strong the storm is, from 0 to 1, it eases towards the state
+    pub thunder: f32,
+    /// Brightness of the last lightning, from 1 when it strikes down to 0
+    #[serde(skip)]
+    pub flash: f32,
+    /// The weather draws its changes from its own rng, which is saved with it
+    rng: Rng,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Self {
+            state: WeatherState::Clear,
+            remaining: 12000,
+            rain: 0.0,
+            thunder: 0.0,
+            flash: 0.0,
+            rng: Rng::new(WORLD_SEED.wrapping_add(WEATHER_SEED)),
+        }
+    }
+}
+
+/// Moves the weather forward by one tick, run with the fixed timestep of the simulation
+///
+/// The changes are drawn from the rng of the weather, so the same seed always brings the same weather,
+/// whichever chunks are loaded and across reloads
+pub fn advance_weather(mut weather: ResMut<Weather>) {
+    let weather = &mut *weather;
+    let rng = &mut weather.rng;
+    if weather.remaining == 0 {
+        let (state, duration) = weather.state.next(rng);
+        weather.state = state;
+        weather.remaining = duration;
+    } else {
+        weather.remaining -= 1;
+    }
+    let step = 1.0 / WEATHER_FADE_TICKS;
+    let rain = if weather.state == WeatherState::Clear { 0.0 } else { 1.0 };
+    let thunder = if weather.state == WeatherState::Thunder { 1.0 } else { 0.0 };
+    weather.rain += (rain - weather.rain).clamp(-step, step);
+    weather.thunder += (thunder - weather.thunder).clamp(-step, step);
+    // about one lightning every 15 seconds in a full storm
+    if weather.thunder > 0.5 && rng.next_below(15 * TICKS_PER_SECOND as u32) == 0 {
+        weather.flash = 1.0;
+    }
+}
+
+/// Darkens and hazes the sky of the preset with the rain, covers it with clouds and lights it up with the lightning
+pub fn weather_sky(
+    time: Res<Time>,
+    mut weather: ResMut<Weather>,
+    transition: Res<AtmosphereTransition>,
+    mut atmosphere: ResMut<Atmosphere>,
+    mut ambient: ResMut<AmbientLight>,
+) {
+    weather.flash = (weather.flash - time.delta_seconds() * 4.0).max(0.0);
+    let clear = transition.current();
+    atmosphere.sun_intensity = clear.sun_intensity * (1.0 - 0.6 * weather.rain - 0.2 * weather.thunder);
+    atmosphere.lightning = weather.flash;
+    atmosphere.mie_coefficient = clear.mie_coefficient * (1.0 + 4.0 * weather.rain);
+    atmosphere.cloud_coverage = clear.cloud_coverage + (0.95 - clear.cloud_coverage) * weather.rain;
+    ambient.brightness = AmbientLight::default().brightness + weather.flash;
+}
+
+/// One of the rain drops or snow flakes falling around the player
+#[derive(Component)]
+pub struct Precipitation {
+    index: usize,
+    velocity: Vec3,
+    /// The drop disappears below this height, on the top block of its column
+    floor: f32,
+    falling: bool,
+}
+
+/// The meshes and materials of the drops
+pub struct PrecipitationAssets {
+    rain_mesh: Handle<Mesh>,
+    rain_material: Handle<StandardMaterial>,
+    snow_mesh: Handle<Mesh>,
+    snow_material: Handle<StandardMaterial>,
+    perlin: Perlin,
+    rng: Rng,
+}
+
+pub fn setup_precipitation(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let assets = PrecipitationAssets {
+        rain_mesh: meshes.add(Mesh::from(shape::Box::new(0.03, 0.6, 0.03))),
+        rain_material: materials.add(StandardMaterial {
+            base_color: Color::rgba(0.6, 0.7, 0.9, 0.5),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+        snow_mesh: meshes.add(Mesh::from(shape::Cube { size: 0.08 })),
+        snow_material: materials.add(StandardMaterial {
+            base_color: Color::rgba(1.0, 1.0, 1.0, 0.9),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+        perlin: Perlin::new(),
+        rng: Rng::new(0),
+    };
+    for index in 0..MAX_DROPS {
+        commands.spawn_bundle(PbrBundle {
+            mesh: assets.rain_mesh.clone(),
+            material: assets.rain_material.clone(),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(Precipitation { index, velocity: Vec3::ZERO, floor: 0.0, falling: false });
+    }
+    commands.insert_resource(assets);
+}
+
+/// Lets rain or snow fall around the player, as much as the weather asks for
+///
+/// Drops only start above columns whose top block is below them and stop on it, so none fall indoors
+pub fn update_precipitation(
+    time: Res<Time>,
+    weather: Res<Weather>,
+    world: Res<VoxelWorld>,
+    registry: Res<BlockRegistry>,
+    assets: Option<ResMut<PrecipitationAssets>>,
+    player: Query<&Transform, (With<Player>, Without<Precipitation>)>,
+    mut drops: Query<(
+        &mut Precipitation,
+        &mut Transform,
+        &mut Visibility,
+        &mut Handle<Mesh>,
+        &mut Handle<StandardMaterial>
+    )>,
+) {
+    let (mut assets, center) = match (assets, player.get_single()) {
+        (Some(assets), Ok(transform)) => (assets, transform.translation),
+        _ => return,
+    };
+    let active = (weather.rain * MAX_DROPS as f32) as usize;
+    for (mut drop, mut transform, mut visibility, mut mesh, mut material) in &mut drops {
+        if drop.index >= active {
+            drop.falling = false;
+        } else if drop.falling && transform.translation.y > drop.floor {
+            transform.translation += drop.velocity * time.delta_seconds();
+            continue;
+        } else {
+            let rng = &mut assets.rng;
+            let x = center.x + (rng.next_f32() * 2.0 - 1.0) * DROP_RADIUS;
+            let z = center.z + (rng.next_f32() * 2.0 - 1.0) * DROP_RADIUS;
+            let y = center.y + 10.0 + rng.next_f32() * 10.0;
+            let (column_x, column_z) = (x.round() as i32, z.round() as i32);
+            // the drop waits for the next frame when its column is covered or not loaded
+            drop.falling = match world.top_block(column_x, column_z, &registry) {
+                Some(top) if (top as f32) < y => {
+                    let snow = temperature(&assets.perlin, column_x, top, column_z) < SNOW_TEMPERATURE;
+                    let drift = Vec3::new(assets.rng.next_f32() - 0.5, 0.0, assets.rng.next_f32() - 0.5);
+                    drop.velocity = if snow { Vec3::new(0.0, -2.0, 0.0) + drift } else { Vec3::new(0.0, -14.0, 0.0) };
+                    drop.floor = top as f32 + 0.5;
+                    transform.translation = Vec3::new(x, y, z);
+                    let (wanted_mesh, wanted_material) = if snow {
+                        (&assets.snow_mesh, &assets.snow_material)
+                    } else {
+                        (&assets.rain_mesh, &assets.rain_material)
+                    };
+                    if *mesh != *wanted_mesh {
+                        *mesh = wanted_mesh.clone();
+                        *material = wanted_material.clone();
+                    }
+                    true
+                },
+                _ => false,
+            };
+        }
+        if visibility.is_visible != drop.falling {
+            visibility.is_visible = drop.falling;
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,283 @@
+use bevy::prelude::*;
+use ndarray::Array3;
+
+use crate::{
+    light::LightChannel,
+    registry::{BlockRegistry, StateId, AIR},
+    section::Section
+};
+
+use std::collections::HashMap;
+
+pub const CHUNK_SIZE: i32 = 16;
+pub const CHUNK_HEIGHT: i32 = 256;
+/// Chunks are split vertically into cubic sections of CHUNK_SIZE
+pub const SECTION_COUNT: i32 = CHUNK_HEIGHT / CHUNK_SIZE;
+
+/// The faces of a block
+///
+/// Every per-face array is indexed in this order, which is also the order of NEIGHBOURS
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Face {
+    /// +X
+    East = 0,
+    /// +Y
+    Up = 1,
+    /// +Z
+    South = 2,
+    /// -X
+    West = 3,
+    /// -Y
+    Down = 4,
+    /// -Z
+    #[default]
+    North = 5,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::East,
+        Face::Up,
+        Face::South,
+        Face::West,
+        Face::Down,
+        Face::North,
+    ];
+
+    /// Offset to the neighbour the face looks at
+    pub fn normal(self) -> IVec3 {
+        NEIGHBOURS[self as usize]
+    }
+
+    pub fn opposite(self) -> Face {
+        Face::ALL[(self as usize + 3) % 6]
+    }
+
+    /// The face whose normal is the given offset
+    pub fn from_normal(normal: IVec3) -> Option<Face> {
+        Face::ALL.into_iter().find(|face| face.normal() == normal)
+    }
+}
+
+/// The six neighbours of a block, in the order of Face
+pub const NEIGHBOURS: [IVec3; 6] = [
+    IVec3::new(1, 0, 0),
+    IVec3::new(0, 1, 0),
+    IVec3::new(0, 0, 1),
+    IVec3::new(-1, 0, 0),
+    IVec3::new(0, -1, 0),
+    IVec3::new(0, 0, -1),
+];
+
+/// Sent every time a block of the world is replaced
+#[derive(Clone, Copy, Debug)]
+pub struct BlockChanged {
+    pub pos: IVec3,
+    pub old: StateId,
+    pub new: StateId,
+}
+
+/// A 16x256x16 column of block states, stored in palette compressed sections from the bottom up
+pub struct Chunk {
+    sections: Vec<Section>,
+    /// Sky light in the high and block light in the low four bits, indexed by local [[x, y, z]]
+    pub light: Array3<u8>,
+}
+
+impl From<Array3<StateId>> for Chunk {
+    fn from(blocks: Array3<StateId>) -> Self {
+        let mut sections = vec![Section::default(); SECTION_COUNT as usize];
+        for ((x, y, z), state) in blocks.indexed_iter() {
+            if *state != AIR {
+                let size = CHUNK_SIZE as usize;
+                sections[y / size].set(Section::index(x, y % size, z), *state);
+            }
+        }
+        let light = Array3::zeros(blocks.dim());
+        Self { sections, light }
+    }
+}
+
+impl Chunk {
+    /// The state at a local [x, y, z]
+    pub fn block(&self, [x, y, z]: [usize; 3]) -> StateId {
+        let size = CHUNK_SIZE as usize;
+        self.sections[y / size].get(Section::index(x, y % size, z))
+    }
+
+    /// Replaces the state at a local [x, y, z] and returns the previous one
+    pub fn set_block(&mut self, [x, y, z]: [usize; 3], state: StateId) -> StateId {
+        let size = CHUNK_SIZE as usize;
+        self.sections[y / size].set(Section::index(x, y % size, z), state)
+    }
+
+    pub fn section(&self, y: i32) -> Option<&Section> {
+        self.sections.get(usize::try_from(y).ok()?)
+    }
+
+    /// Bytes used by the block states of the chunk
+    pub fn block_memory(&self) -> usize {
+        self.sections.iter().map(Section::memory).sum()
+    }
+}
+
+/// Read access to the blocks and light of a part of the world
+///
+/// Implemented by the world itself and by the copies which background jobs work on
+pub trait BlockView {
+    /// None when the position is outside of the available blocks
+    fn get_block_opt(&self, pos: IVec3) -> Option<StateId>;
+
+    /// None when the position is outside of the available blocks
+    fn light(&self, pos: IVec3, channel: LightChannel) -> Option<u8>;
+
+    fn get_block(&self, pos: IVec3) -> StateId {
+        self.get_block_opt(pos).unwrap_or(AIR)
+    }
+}
+
+/// The block data of all loaded chunks
+///
+/// This is the source of truth for the terrain, the chunk meshes and colliders only mirror it
+#[derive(Default)]
+pub struct VoxelWorld {
+    chunks: HashMap<IVec2, Chunk>,
+    changes: Vec<BlockChanged>,
+}
+
+impl VoxelWorld {
+    /// Coordinate of the 16x16x16 section containing pos
+    pub fn section_coord(pos: IVec3) -> IVec3 {
+        IVec3::new(
+            pos.x.div_euclid(CHUNK_SIZE),
+            pos.y.div_euclid(CHUNK_SIZE),
+            pos.z.div_euclid(CHUNK_SIZE),
+        )
+    }
+
+    pub fn chunk_coord(pos: IVec3) -> IVec2 {
+        IVec2::new(pos.x.div_euclid(CHUNK_SIZE), pos.z.div_euclid(CHUNK_SIZE))
+    }
+
+    fn local_index(pos: IVec3) -> [usize; 3] {
+        [
+            pos.x.rem_euclid(CHUNK_SIZE) as usize,
+            pos.y as usize,
+            pos.z.rem_euclid(CHUNK_SIZE) as usize,
+        ]
+    }
+
+    pub fn insert_chunk(&mut self, coord: IVec2, chunk: Chunk) {
+        self.chunks.insert(coord, chunk);
+    }
+
+    pub fn remove_chunk(&mut self, coord: IVec2) -> Option<Chunk> {
+        self.chunks.remove(&coord)
+    }
+
+    pub fn chunk(&self, coord: IVec2) -> Option<&Chunk> {
+        self.chunks.get(&coord)
+    }
+
+    /// Coordinates of the loaded chunks, in no particular order
+    pub fn chunk_coords(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    /// None when the position is outside of the loaded chunks
+    pub fn get_block_opt(&self, pos: IVec3) -> Option<StateId> {
+        if pos.y < 0 || pos.y >= CHUNK_HEIGHT {
+            return None;
+        }
+        self.chunks
+            .get(&Self::chunk_coord(pos))
+            .map(|chunk| chunk.block(Self::local_index(pos)))
+    }
+
+    pub fn get_block(&self, pos: IVec3) -> StateId {
+        self.get_block_opt(pos).unwrap_or(AIR)
+    }
+
+    /// Height of the highest solid block of a column, rain and snow land on it
+    ///
+    /// Air and the replaceable blocks like water and tall grass are skipped,
+    /// None when the column is not loaded or holds no solid block
+    pub fn top_block(&self, x: i32, z: i32, registry: &BlockRegistry) -> Option<i32> {
+        let chunk = self.chunks.get(&Self::chunk_coord(IVec3::new(x, 0, z)))?;
+        let [x, _, z] = Self::local_index(IVec3::new(x, 0, z));
+        (0..CHUNK_HEIGHT).rev().find(|y| {
+            let state = chunk.block([x, *y as usize, z]);
+            state != AIR && !registry.def(state).replaceable
+        })
+    }
+
+    /// Whether the section holds nothing but air, unloaded sections count as empty
+    pub fn is_section_empty(&self, section: IVec3) -> bool {
+        self.chunks
+            .get(&IVec2::new(section.x, section.z))
+            .and_then(|chunk| chunk.section(section.y))
+            .map_or(true, Section::is_empty)
+    }
+
+    /// Bytes used by the block states of the loaded chunks, and what they would use as plain u32 arrays
+    pub fn block_memory(&self) -> (usize, usize) {
+        let packed = self.chunks.values().map(Chunk::block_memory).sum();
+        let raw = self.chunks.len() * (CHUNK_SIZE * CHUNK_HEIGHT * CHUNK_SIZE) as usize * std::mem::size_of::<StateId>();
+        (packed, raw)
+    }
+
+    /// None when the position is outside of the loaded chunks
+    pub fn light(&self, pos: IVec3, channel: LightChannel) -> Option<u8> {
+        if pos.y < 0 || pos.y >= CHUNK_HEIGHT {
+            return None;
+        }
+        self.chunks
+            .get(&Self::chunk_coord(pos))
+            .map(|chunk| channel.unpack(chunk.light[Self::local_index(pos)]))
+    }
+
+    pub fn set_light(&mut self, pos: IVec3, channel: LightChannel, level: u8) {
+        if pos.y < 0 || pos.y >= CHUNK_HEIGHT {
+            return;
+        }
+        if let Some(chunk) = self.chunks.get_mut(&Self::chunk_coord(pos)) {
+            let packed = &mut chunk.light[Self::local_index(pos)];
+            *packed = channel.pack(*packed, level);
+        }
+    }
+
+    /// Replaces a block state and queues a BlockChanged for it
+    ///
+    /// Returns the previous state, or None if the position is not loaded
+    pub fn set_block(&mut self, pos: IVec3, id: StateId) -> Option<StateId> {
+        if pos.y < 0 || pos.y >= CHUNK_HEIGHT {
+            return None;
+        }
+        let chunk = self.chunks.get_mut(&Self::chunk_coord(pos))?;
+        let old = chunk.set_block(Self::local_index(pos), id);
+        if old != id {
+            self.changes.push(BlockChanged { pos, old, new: id });
+        }
+        Some(old)
+    }
+}
+
+impl BlockView for VoxelWorld {
+    fn get_block_opt(&self, pos: IVec3) -> Option<StateId> {
+        VoxelWorld::get_block_opt(self, pos)
+    }
+
+    fn light(&self, pos: IVec3, channel: LightChannel) -> Option<u8> {
+        VoxelWorld::light(self, pos, channel)
+    }
+}
+
+/// Sends the changes made to the world since the last frame
+pub fn emit_block_changes(
+    mut world: ResMut<VoxelWorld>,
+    mut events: EventWriter<BlockChanged>,
+) {
+    for change in world.changes.drain(..) {
+        events.send(change);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,166 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::f32::consts::TAU;
+
+/// Length of a day when the save file does not set one, 20 minutes at 20 ticks per second
+pub const DEFAULT_DAY_LENGTH: u64 = 24000;
+
+/// Number of days in a lunar cycle, the first day of a cycle has a full moon
+pub const MOON_PHASES: u64 = 8;
+
+/// The time of the world, counted in simulation ticks, it only moves while it is not frozen
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WorldTime {
+    /// Ticks since the world was created, a day starts at sunrise
+    pub tick: u64,
+    pub day_length: u64,
+    pub frozen: bool,
+}
+
+impl Default for WorldTime {
+    fn default() -> Self {
+        Self {
+            tick: 0,
+            day_length: DEFAULT_DAY_LENGTH,
+            frozen: false,
+        }
+    }
+}
+
+impl WorldTime {
+    /// Number of the current day, the first day is 0
+    pub fn day(&self) -> u64 {
+        self.tick / self.day_length.max(1)
+    }
+
+    /// Ticks since the last sunrise
+    pub fn time_of_day(&self) -> u64 {
+        self.tick % self.day_length.max(1)
+    }
+
+    /// Angle of the sun above the eastern horizon, in radians, noon is at a quarter turn
+    pub fn sun_angle(&self) -> f32 {
+        self.time_of_day() as f32 / self.day_length.max(1) as f32 * TAU
+    }
+
+    /// Phase of the moon tonight, from 0 for a full moon to MOON_PHASES - 1
+    pub fn moon_phase(&self) -> u64 {
+        self.day() % MOON_PHASES
+    }
+
+    /// Part of the lunar cycle that has passed, from 0 at full moon up to 1
+    pub fn moon_cycle(&self) -> f32 {
+        self.moon_phase() as f32 / MOON_PHASES as f32
+    }
+
+    /// Lit part of the moon disc as seen from the world, 1 at full moon and 0 at new moon
+    pub fn moon_brightness(&self) -> f32 {
+        (1.0 + (self.moon_cycle() * TAU).cos()) / 2.0
+    }
+
+    pub fn apply(&mut self, command: TimeCommand) {
+        match command {
+            TimeCommand::Set(time_of_day) => {
+                self.tick = self.day() * self.day_length + time_of_day % self.day_length.max(1)
+            },
+            TimeCommand::Add(ticks) => self.tick += ticks,
+            TimeCommand::Freeze(frozen) => self.frozen = frozen,
+        }
+    }
+}
+
+/// A change to the world time, sent as an event
+#[derive(Clone, Copy, Debug)]
+pub enum TimeCommand {
+    /// Moves to this many ticks after the sunrise of the current day
+    Set(u64),
+    /// Moves the time forward by this many ticks
+    Add(u64),
+    /// Stops or restarts the time
+    Freeze(bool),
+}
+
+/// Moves the time forward by one tick, run with the fixed timestep of the simulation
+pub fn advance_world_time(mut time: ResMut<WorldTime>) {
+    if !time.frozen {
+        time.tick += 1;
+    }
+}
+
+/// Sends time commands from the keyboard, F6 freezes the time, F7 skips an hour and F8 sets the time to noon
+pub fn time_keys(
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<WorldTime>,
+    mut commands: EventWriter<TimeCommand>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        commands.send(TimeCommand::Freeze(!time.frozen));
+    }
+    if keyboard_input.just_pressed(KeyCode::F7) {
+        commands.send(TimeCommand::Add(time.day_length / 24));
+    }
+    if keyboard_input.just_pressed(KeyCode::F8) {
+        commands.send(TimeCommand::Set(time.day_length / 4));
+    }
+}
+
+pub fn apply_time_commands(
+    mut commands: EventReader<TimeCommand>,
+    mut time: ResMut<WorldTime>,
+) {
+    for command in commands.iter() {
+        time.apply(*command);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(tick: u64, day_length: u64) -> WorldTime {
+        WorldTime { tick, day_length, frozen: false }
+    }
+
+    #[test]
+    fn set_stays_on_the_current_day() {
+        let mut time = time(3 * 1000 + 900, 1000);
+        time.apply(TimeCommand::Set(250));
+        assert_eq!(time.tick, 3 * 1000 + 250);
+        assert_eq!(time.day(), 3);
+        // a time past the end of the day wraps around instead of moving to the next day
+        time.apply(TimeCommand::Set(1100));
+        assert_eq!(time.tick, 3 * 1000 + 100);
+    }
+
+    #[test]
+    fn add_moves_across_days() {
+        let mut time = time(900, 1000);
+        time.apply(TimeCommand::Add(250));
+        assert_eq!(time.day(), 1);
+        assert_eq!(time.time_of_day(), 150);
+    }
+
+    #[test]
+    fn zero_day_length_does_not_divide_by_zero() {
+        let mut time = time(5, 0);
+        assert_eq!(time.day(), 5);
+        assert_eq!(time.time_of_day(), 0);
+        assert_eq!(time.sun_angle(), 0.0);
+        time.apply(TimeCommand::Set(3));
+        assert_eq!(time.tick, 0);
+    }
+
+    #[test]
+    fn moon_waxes_and_wanes_over_the_cycle() {
+        let brightness = |day: u64| time(day * 1000, 1000).moon_brightness();
+        assert!((brightness(0) - 1.0).abs() < 1e-6);
+        assert!(brightness(MOON_PHASES / 2) < 1e-6);
+        assert!((brightness(MOON_PHASES) - 1.0).abs() < 1e-6);
+        for day in 1..MOON_PHASES / 2 {
+            assert!(brightness(day) < brightness(day - 1), "day {}", day);
+            assert!((brightness(day) - brightness(MOON_PHASES - day)).abs() < 1e-6, "day {}", day);
+        }
+    }
+}
\ No newline at end of file